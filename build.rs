@@ -40,6 +40,7 @@ fn fix(path: PathBuf) {
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // build protobuffer code using tonic 
     tonic_build::compile_protos("rpc_proto/proto/helloworld.proto")?;
+    tonic_build::compile_protos("rpc_proto/proto/container_agent.proto")?;
 
     // build capnp code using capnp
     capnpc::CompilerCommand::new()
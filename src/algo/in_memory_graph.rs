@@ -4,7 +4,9 @@ use std::fmt;
 
 use serde::de::DeserializeOwned;
 
-use super::traits::{PartitionBin, Partition};
+use super::traits::{PartitionBin, PartitionItem, Partition};
+use super::min_cut;
+use super::strategy::{self, PartitionStrategy};
 
 type Result<T> = std::result::Result<T, String>;
 
@@ -17,9 +19,11 @@ fn reverse_edge_id<T: Clone>(edge_id: &EdgeId<T>) -> ReverseEdgeId<T> {
     (edge_id.1.clone(), edge_id.0.clone())
 }
 
+// Parallel edges connecting the same pair of vertexes are stored under the edge id 
+// of the first one, regardless of their directions.
 pub struct InMemoryGraph<Vid, Vertex, Edge> {
     vertexes: HashMap<Vid, Vertex>,
-    edges: BTreeMap<EdgeId<Vid>, Edge>, 
+    edges: BTreeMap<EdgeId<Vid>, Vec<Edge>>, 
     reverse_edges: BTreeMap<ReverseEdgeId<Vid>, ()> 
 }
 
@@ -53,16 +57,16 @@ where
             if !vertex_map.contains_key(&eid.0) || !vertex_map.contains_key(&eid.1) {
                 return Err("edge is not connected to a valid vertex".to_string());
             }
-            // insert edges into the map, report error on edge id collision
+            // insert edges into the map, a repeated edge id is a parallel edge
             let reverse_eid = reverse_edge_id(&eid);
-            if edge_map.contains_key(&eid) || edge_map.contains_key(&reverse_eid) {
-                Err("repreated edge id".to_string())
+            if let Some(parallel_edges) = edge_map.get_mut(&reverse_eid) {
+                parallel_edges.push(e);
             }
             else {
-                edge_map.insert(eid, e);
+                edge_map.entry(eid).or_insert_with(Vec::new).push(e);
                 reverse_edge_map.insert(reverse_eid, ());
-                Ok(())
             }
+            Ok(())
         }).collect();
         let _ = insert_res?;
 
@@ -75,50 +79,115 @@ where
 
     pub fn into(self) -> (Vec<Vertex>, Vec<Edge>) {
         let vertexes = self.vertexes.into_iter().map(|(_, vertex)| {vertex});
-        let edges = self.edges.into_iter().map(|(_, edge)| {edge});
+        let edges = self.edges.into_iter().flat_map(|(_, edges)| {edges.into_iter()});
         (vertexes.collect(), edges.collect())
     }
 
     pub fn size(&self) -> usize {
         self.vertexes.len()
     }
+
+    pub fn vertexes<'a>(&'a self) -> impl Iterator<Item = (&'a Vid, &'a Vertex)> {
+        self.vertexes.iter()
+    }
+
+    /// Iterate through the edges, the parallel edges share the same edge id.
+    pub fn edges<'a>(&'a self) -> impl Iterator<Item = (&'a EdgeId<Vid>, &'a Edge)> {
+        self.edges.iter().flat_map(|(eid, edges)| {edges.iter().map(move |e| {(eid, e)})})
+    }
+
+    pub fn edges_mut<'a>(&'a mut self) -> impl Iterator<Item = (&'a EdgeId<Vid>, &'a mut Edge)> {
+        self.edges.iter_mut().flat_map(|(eid, edges)| {edges.iter_mut().map(move |e| {(eid, e)})})
+    }
 }
 
-impl<'a, Vid, Vertex, Edge, T, I> Partition<'a, T, I> for InMemoryGraph<Vid, Vertex, Edge>
+impl<Vid, Vertex, Edge> InMemoryGraph<Vid, Vertex, Edge>
 where
-    T: 'a + PartitionBin<Size = u32>,
-    I: Iterator<Item = &'a mut T>,
-    Vid: Eq + Hash + Clone
+    Vid: Eq + Ord + Hash + Clone
 {
-    type ItemId = Vid;
+    // Index the vertexes in the order of their ids, and build up the adjacency lists.
+    fn adjacency(&self) -> (Vec<Vid>, Vec<Vec<usize>>) {
+        let mut vids: Vec<Vid> = self.vertexes.keys().cloned().collect();
+        vids.sort();
+        let idx: HashMap<&Vid, usize> = vids.iter().enumerate().map(|(i, vid)| {(vid, i)}).collect();
 
-    fn partition(&self, mut bins: I) -> Result<HashMap<Vid, <T as PartitionBin>::BinId>> 
-    {
-        // acquire an iterator of vids
-        let mut vids = self.vertexes.keys();
-        // retrieve the first bin
-        let mut curr_bin = bins.next().ok_or("not enough resource".to_string())?;
-        // initialize the resulting HashMap
-        let mut res = HashMap::new();
-        
-        // iterate through all the vids and make assignment
-        while let Some(vid) = vids.next() {
-            if curr_bin.fill(1) {
-                res.insert(vid.clone(), curr_bin.bin_id());
-            }
-            else {
-                if let Some(new_bin) = bins.next() {
-                    curr_bin = new_bin;
-                }
-                else {
-                    return Err("not enough resource".to_string());
+        let mut adj = vec![Vec::new(); vids.len()];
+        for (eid, edges) in self.edges.iter() {
+            let (v, u) = (*idx.get(&eid.0).unwrap(), *idx.get(&eid.1).unwrap());
+            // a self loop is never cut, and each parallel edge is counted
+            for _ in edges.iter() {
+                if v != u {
+                    adj[v].push(u);
+                    adj[u].push(v);
                 }
             }
         }
+        (vids, adj)
+    }
+
+    /// Partition the vertexes into bins with the given strategy.
+    ///
+    /// Return the mapping from the vertex id to bin id, and the number of edges
+    /// whose end vertexes are placed into different bins.
+    pub fn partition_with<'a, T, I>(&self, strategy: PartitionStrategy, bins: I) 
+        -> Result<(HashMap<Vid, <T as PartitionBin>::BinId>, usize)>
+    where
+        T: 'a + PartitionBin,
+        <T as PartitionBin>::Size: Clone + Ord,
+        <T as PartitionBin>::BinId: Clone,
+        I: Iterator<Item = &'a mut T>,
+        Vertex: PartitionItem<Size = <T as PartitionBin>::Size>,
+    {
+        let (vids, adj) = self.adjacency();
+        let sizes: Vec<<T as PartitionBin>::Size> = vids.iter().map(|vid| {self.vertexes.get(vid).unwrap().size()}).collect();
+        let (part, bin_ids) = match strategy {
+            PartitionStrategy::Sequential => strategy::sequential(&sizes, bins)?,
+            PartitionStrategy::RoundRobin => strategy::round_robin(&sizes, bins)?,
+            PartitionStrategy::MinCut => min_cut::min_cut_partition(&adj, &sizes, bins)?,
+            PartitionStrategy::Pack => {
+                let mut bins: Vec<&'a mut T> = bins.collect();
+                bins.sort_by(|b0, b1| {b1.remaining().cmp(&b0.remaining())});
+                min_cut::min_cut_partition(&adj, &sizes, bins.into_iter())?
+            }
+        };
+        let cut = min_cut::cut_size(&adj, &part);
+
+        let res = vids.into_iter().zip(part.into_iter()).map(|(vid, bin)| {
+            (vid, bin_ids[bin].clone())
+        }).collect();
+        Ok((res, cut))
+    }
+}
 
-        Ok(res)
+/// Partition the vertexes of a graph with a strategy.
+pub struct StrategyPartitioner<'g, Vid, Vertex, Edge> {
+    graph: &'g InMemoryGraph<Vid, Vertex, Edge>,
+    strategy: PartitionStrategy,
+}
+
+impl<Vid, Vertex, Edge> InMemoryGraph<Vid, Vertex, Edge> {
+    pub fn partitioner(&self, strategy: PartitionStrategy) -> StrategyPartitioner<'_, Vid, Vertex, Edge> {
+        StrategyPartitioner {
+            graph: self,
+            strategy,
+        }
     }
+}
 
+impl<'a, 'g, Vid, Vertex, Edge, T, I> Partition<'a, T, I> for StrategyPartitioner<'g, Vid, Vertex, Edge>
+where
+    T: 'a + PartitionBin,
+    <T as PartitionBin>::Size: Clone + Ord,
+    <T as PartitionBin>::BinId: Clone,
+    I: Iterator<Item = &'a mut T>,
+    Vid: Eq + Ord + Hash + Clone,
+    Vertex: PartitionItem<Size = <T as PartitionBin>::Size>,
+{
+    type ItemId = Vid;
+
+    fn partition(&self, bins: I) -> Result<HashMap<Vid, <T as PartitionBin>::BinId>> {
+        self.graph.partition_with(self.strategy, bins).map(|(assignment, _)| {assignment})
+    }
 }
 
 impl<Vid, Vertex, Edge> InMemoryGraph<Vid, Vertex, Edge>
@@ -132,4 +201,148 @@ where
         println!("{:?}", self.edges);
         println!("{:?}", self.reverse_edges);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use serde::Deserialize;
+
+    use super::{InMemoryGraph, Partition, PartitionBin, PartitionItem, PartitionStrategy};
+    use crate::algo::check_partition;
+
+    #[derive(Deserialize)]
+    struct Item(u32);
+
+    impl PartitionItem for Item {
+        type Size = u32;
+
+        fn size(&self) -> u32 {
+            self.0
+        }
+    }
+
+    struct Bin {
+        id: usize,
+        remaining: u32,
+    }
+
+    impl PartitionBin for Bin {
+        type Size = u32;
+        type BinId = usize;
+
+        fn fill(&mut self, item_size: u32) -> bool {
+            if self.remaining < item_size {
+                return false;
+            }
+            self.remaining -= item_size;
+            true
+        }
+
+        fn bin_id(&self) -> usize {
+            self.id
+        }
+
+        fn remaining(&self) -> u32 {
+            self.remaining
+        }
+    }
+
+    const STRATEGIES: [PartitionStrategy; 4] = [
+        PartitionStrategy::Sequential, 
+        PartitionStrategy::RoundRobin, 
+        PartitionStrategy::MinCut, 
+        PartitionStrategy::Pack,
+    ];
+
+    fn build_graph(sizes: &Vec<u32>, edges: &Vec<(u64, u64)>) -> InMemoryGraph<u64, Item, ()> {
+        InMemoryGraph::from_vecs(
+            sizes.iter().enumerate().map(|(id, size)| {(id as u64, Item(*size))}).collect(),
+            edges.iter().map(|eid| {(*eid, ())}).collect(),
+        ).unwrap()
+    }
+
+    fn build_bins(capacities: &Vec<u32>) -> Vec<Bin> {
+        capacities.iter().enumerate().map(|(id, capacity)| {Bin { id, remaining: *capacity }}).collect()
+    }
+
+    // vertex sizes, edges and bin capacities
+    fn graph_and_bins(max_size: u32) -> impl Strategy<Value = (Vec<u32>, Vec<(u64, u64)>, Vec<u32>)> {
+        (1usize..30).prop_flat_map(move |n| {(
+            prop::collection::vec(1..max_size + 1, n),
+            prop::collection::vec((0..n as u64, 0..n as u64), 0..60),
+            prop::collection::vec(0u32..20, 1..6),
+        )})
+    }
+
+    // run `partitioner` on fresh bins and check the assignment if the partition succeeds,
+    // return whether it succeeds, any implementation of `Partition` can be checked with it
+    fn check_partitioner<P>(partitioner: &P, sizes: &Vec<u32>, capacities: &Vec<u32>) -> Result<bool, TestCaseError> 
+    where
+        P: for<'a> Partition<'a, Bin, std::slice::IterMut<'a, Bin>, ItemId = u64>,
+    {
+        let mut filled = build_bins(capacities);
+        let assignment = match partitioner.partition(filled.iter_mut()) {
+            Ok(assignment) => assignment,
+            Err(_) => return Ok(false),
+        };
+        let ids: Vec<u64> = (0..sizes.len() as u64).collect();
+        let items = ids.iter().zip(sizes.iter()).map(|(id, size)| {(id, *size)});
+        prop_assert_eq!(check_partition(items, &assignment, build_bins(capacities)), Ok(()));
+
+        // every vertex is assigned exactly once, and no bin exceeds its capacity
+        prop_assert_eq!(assignment.len(), sizes.len());
+        let mut used = vec![0; capacities.len()];
+        for (id, size) in sizes.iter().enumerate() {
+            let bin = assignment.get(&(id as u64));
+            prop_assert!(bin.is_some());
+            used[*bin.unwrap()] += size;
+        }
+        for bin in filled.iter() {
+            prop_assert!(used[bin.id] <= capacities[bin.id]);
+            prop_assert_eq!(bin.remaining, capacities[bin.id] - used[bin.id]);
+        }
+        Ok(true)
+    }
+
+    #[test]
+    fn repeated_edge_ids_are_parallel_edges() {
+        let graph: InMemoryGraph<u64, (), &str> = InMemoryGraph::from_vecs(
+            vec!((0, ()), (1, ()), (2, ())),
+            vec!(((0, 1), "a"), ((1, 0), "b"), ((0, 1), "c"), ((1, 2), "d")),
+        ).unwrap();
+        // a reversed edge id is stored under the edge id seen first
+        let edges: Vec<((u64, u64), &str)> = graph.edges().map(|(eid, e)| {(*eid, *e)}).collect();
+        assert_eq!(edges, vec!(((0, 1), "a"), ((0, 1), "b"), ((0, 1), "c"), ((1, 2), "d")));
+        assert_eq!(graph.into().1.len(), 4);
+
+        let res = InMemoryGraph::<u64, (), ()>::from_vecs(vec!((0, ())), vec!(((0, 1), ())));
+        assert_eq!(res.err().unwrap(), "edge is not connected to a valid vertex");
+    }
+
+    proptest! {
+        #[test]
+        fn partition_respects_capacity((sizes, edges, capacities) in graph_and_bins(3)) {
+            let graph = build_graph(&sizes, &edges);
+            for strategy in STRATEGIES.iter() {
+                check_partitioner(&graph.partitioner(*strategy), &sizes, &capacities)?;
+
+                // only the given edges can be cut
+                let mut filled = build_bins(&capacities);
+                if let Ok((_, cut)) = graph.partition_with(*strategy, filled.iter_mut()) {
+                    prop_assert!(cut <= edges.len());
+                }
+            }
+        }
+
+        #[test]
+        fn unit_partition_succeeds_with_enough_capacity((sizes, edges, capacities) in graph_and_bins(1)) {
+            let graph = build_graph(&sizes, &edges);
+            let enough = capacities.iter().sum::<u32>() as usize >= sizes.len();
+            for strategy in STRATEGIES.iter() {
+                let succeeded = check_partitioner(&graph.partitioner(*strategy), &sizes, &capacities)?;
+                prop_assert!(succeeded || !enough, "{:?} fails with enough capacity", strategy);
+            }
+        }
+    }
+}
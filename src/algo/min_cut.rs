@@ -0,0 +1,190 @@
+// An edge-cut-minimizing partitioner.
+//
+// The vertexes are represented by the indexes 0..n, and the graph is represented
+// by the adjacency list of each vertex. A parallel edge shows up multiple times in the
+// adjacency list, and increases the weight of the connection between the two vertexes.
+//
+// The partition is done in two phases:
+// 1. Greedy growing: each bin is filled with a connected region grown from a seed vertex,
+// always picking the unassigned vertex with the most connections to the current bin.
+// A vertex that does not fit in the current bin is skipped until the next bin.
+// 2. Kernighan-Lin style refinement: pairs of vertexes of the same size in different bins
+// are swapped as long as the swap reduces the cut size. Swapping keeps the filled size
+// of each bin unchanged, so the capacity of the bins is still respected.
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use super::traits::PartitionBin;
+
+type Result<T> = std::result::Result<T, String>;
+
+/// Partition the vertexes into the bins while minimizing the number of cut edges.
+///
+/// `sizes` holds the size of each vertex.
+/// Return the bin index of each vertex and the ids of the used bins.
+pub fn min_cut_partition<'a, T, I>(adj: &Vec<Vec<usize>>, sizes: &Vec<T::Size>, mut bins: I) 
+    -> Result<(Vec<usize>, Vec<T::BinId>)>
+where
+    T: 'a + PartitionBin,
+    T::Size: Clone + PartialEq,
+    I: Iterator<Item = &'a mut T>,
+{
+    let mut parts = grow(adj, sizes, &mut bins)?;
+    refine(adj, sizes, &mut parts.0, parts.1.len());
+    Ok(parts)
+}
+
+/// Count the edges whose end vertexes are assigned to different bins.
+pub fn cut_size(adj: &Vec<Vec<usize>>, part: &Vec<usize>) -> usize {
+    // each edge shows up twice in the adjacency lists
+    adj.iter().enumerate().fold(0, |cut, (v, nbrs)| {
+        cut + nbrs.iter().filter(|u| {part[**u] != part[v]}).count()
+    }) / 2
+}
+
+// Phase 1: fill the bins one by one with connected regions.
+fn grow<'a, T, I>(adj: &Vec<Vec<usize>>, sizes: &Vec<T::Size>, bins: &mut I) -> Result<(Vec<usize>, Vec<T::BinId>)>
+where
+    T: 'a + PartitionBin,
+    T::Size: Clone,
+    I: Iterator<Item = &'a mut T>,
+{
+    let n = adj.len();
+    let mut part: Vec<Option<usize>> = vec![None; n];
+    let mut bin_ids = Vec::new();
+    let mut assigned = 0;
+
+    while assigned < n {
+        let bin = bins.next().ok_or("not enough resource".to_string())?;
+        let bin_idx = bin_ids.len();
+        bin_ids.push(bin.bin_id());
+
+        // the number of connections from each unassigned vertex to the current bin,
+        // the heap may hold stale entries, which are skipped when popped
+        let mut conn: Vec<u32> = vec![0; n];
+        let mut heap: BinaryHeap<(u32, Reverse<usize>)> = BinaryHeap::new();
+        // the vertexes that do not fit in the current bin
+        let mut skipped: Vec<bool> = vec![false; n];
+        // the next seed candidate when the current region can not grow any more
+        let mut seed = 0;
+
+        while assigned < n {
+            // pick the most connected vertex, or a new seed if there is none
+            let mut picked = None;
+            while let Some((c, Reverse(v))) = heap.pop() {
+                if part[v].is_none() && !skipped[v] && conn[v] == c {
+                    picked = Some(v);
+                    break;
+                }
+            }
+            if picked.is_none() {
+                while seed < n && (part[seed].is_some() || skipped[seed]) {
+                    seed += 1;
+                }
+                if seed < n {
+                    picked = Some(seed);
+                }
+            }
+            let v = match picked {
+                Some(v) => v,
+                // none of the unassigned vertexes fits in the current bin
+                None => break,
+            };
+
+            if !bin.fill(sizes[v].clone()) {
+                skipped[v] = true;
+                continue;
+            }
+            part[v] = Some(bin_idx);
+            assigned += 1;
+            for u in adj[v].iter().cloned() {
+                if part[u].is_none() {
+                    conn[u] += 1;
+                    heap.push((conn[u], Reverse(u)));
+                }
+            }
+        }
+    }
+
+    Ok((part.into_iter().map(|p| {p.unwrap()}).collect(), bin_ids))
+}
+
+// Phase 2: swap vertex pairs between bins until no swap reduces the cut size.
+fn refine<S: PartialEq>(adj: &Vec<Vec<usize>>, sizes: &Vec<S>, part: &mut Vec<usize>, bin_count: usize) {
+    // the number of neighbours of each vertex in each bin
+    let mut nbr_count: Vec<HashMap<usize, i64>> = adj.iter().map(|nbrs| {
+        nbrs.iter().fold(HashMap::new(), |mut map, u| {
+            *map.entry(part[*u]).or_insert(0) += 1;
+            map
+        })
+    }).collect();
+    let count = |nbr_count: &Vec<HashMap<usize, i64>>, v: usize, bin: usize| -> i64 {
+        nbr_count[v].get(&bin).cloned().unwrap_or(0)
+    };
+
+    // every swap reduces the cut size by at least 1, so the loop terminates
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for a in 0..bin_count {
+            for b in (a+1)..bin_count {
+                while let Some((v, u)) = best_swap(adj, sizes, part, &nbr_count, &count, a, b) {
+                    // move v from a to b, and u from b to a
+                    for (x, from, to) in vec!((v, a, b), (u, b, a)).into_iter() {
+                        part[x] = to;
+                        for y in adj[x].iter().cloned() {
+                            *nbr_count[y].get_mut(&from).unwrap() -= 1;
+                            *nbr_count[y].entry(to).or_insert(0) += 1;
+                        }
+                    }
+                    improved = true;
+                }
+            }
+        }
+    }
+}
+
+// Find the swap between bin a and bin b that reduces the cut size the most.
+fn best_swap<S, F>(adj: &Vec<Vec<usize>>, sizes: &Vec<S>, part: &Vec<usize>, nbr_count: &Vec<HashMap<usize, i64>>, 
+                   count: &F, a: usize, b: usize) -> Option<(usize, usize)>
+where
+    S: PartialEq,
+    F: Fn(&Vec<HashMap<usize, i64>>, usize, usize) -> i64
+{
+    // the gain of moving each vertex to the other bin, in descending order
+    let gains = |from: usize, to: usize| -> Vec<(i64, usize)> {
+        let mut gains: Vec<(i64, usize)> = (0..part.len()).filter(|v| {part[*v] == from}).map(|v| {
+            (count(nbr_count, v, to) - count(nbr_count, v, from), v)
+        }).collect();
+        gains.sort_by(|x, y| {y.cmp(x)});
+        gains
+    };
+    let gains_a = gains(a, b);
+    let gains_b = gains(b, a);
+
+    let mut best: Option<(usize, usize)> = None;
+    let mut best_gain = 0;
+    for (ga, v) in gains_a.iter().cloned() {
+        if gains_b.is_empty() || ga + gains_b[0].0 <= best_gain {
+            break;
+        }
+        for (gb, u) in gains_b.iter().cloned() {
+            if ga + gb <= best_gain {
+                break;
+            }
+            // only the vertexes of the same size can be swapped
+            if sizes[v] != sizes[u] {
+                continue;
+            }
+            // an edge between v and u stays cut after the swap
+            let shared = adj[v].iter().filter(|x| {**x == u}).count() as i64;
+            let gain = ga + gb - 2 * shared;
+            if gain > best_gain {
+                best_gain = gain;
+                best = Some((v, u));
+            }
+        }
+    }
+
+    best
+}
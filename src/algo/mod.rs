@@ -1,5 +1,10 @@
 pub mod in_memory_graph;
 
+mod min_cut;
+mod strategy;
 mod traits;
 pub use traits::PartitionBin;
-pub use traits::Partition;
\ No newline at end of file
+pub use traits::PartitionItem;
+pub use traits::Partition;
+pub use traits::check_partition;
+pub use strategy::PartitionStrategy;
\ No newline at end of file
@@ -0,0 +1,87 @@
+// Simple placement strategies that ignore the topology.
+//
+// Like the min-cut partitioner, the vertexes are represented by the indexes 0..n.
+use serde::{Deserialize, Serialize};
+
+use super::traits::PartitionBin;
+
+type Result<T> = std::result::Result<T, String>;
+
+/// The named strategies for placing the vertexes into the bins.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionStrategy {
+    /// Fill the bins one by one, in the order of the vertex ids.
+    Sequential,
+    /// Place the vertexes on the bins in turn, spreading the load evenly.
+    RoundRobin,
+    /// Place connected vertexes in the same bin whenever possible.
+    MinCut,
+    /// Place connected vertexes together on as few bins as possible, filling the largest bins first.
+    Pack,
+}
+
+impl Default for PartitionStrategy {
+    fn default() -> Self {
+        PartitionStrategy::MinCut
+    }
+}
+
+/// Fill the bins one by one.
+///
+/// `sizes` holds the size of each vertex.
+/// Return the bin index of each vertex and the ids of the used bins.
+pub fn sequential<'a, T, I>(sizes: &Vec<T::Size>, mut bins: I) -> Result<(Vec<usize>, Vec<T::BinId>)>
+where
+    T: 'a + PartitionBin,
+    T::Size: Clone,
+    I: Iterator<Item = &'a mut T>,
+{
+    let mut part = Vec::with_capacity(sizes.len());
+    let mut bin_ids = Vec::new();
+    let mut curr_bin: Option<&'a mut T> = None;
+
+    for size in sizes.iter() {
+        // move on to the next bin until the vertex fits in
+        loop {
+            if let Some(bin) = curr_bin.as_mut() {
+                if bin.fill(size.clone()) {
+                    break;
+                }
+            }
+            let bin = bins.next().ok_or("not enough resource".to_string())?;
+            bin_ids.push(bin.bin_id());
+            curr_bin = Some(bin);
+        }
+        part.push(bin_ids.len() - 1);
+    }
+
+    Ok((part, bin_ids))
+}
+
+/// Place the vertexes on the bins in turn, skipping the bins that the vertex does not fit in.
+///
+/// `sizes` holds the size of each vertex.
+/// Return the bin index of each vertex and the ids of the bins.
+pub fn round_robin<'a, T, I>(sizes: &Vec<T::Size>, bins: I) -> Result<(Vec<usize>, Vec<T::BinId>)>
+where
+    T: 'a + PartitionBin,
+    T::Size: Clone,
+    I: Iterator<Item = &'a mut T>,
+{
+    let mut bins: Vec<&'a mut T> = bins.collect();
+    let bin_ids = bins.iter().map(|bin| {bin.bin_id()}).collect();
+    let mut cursor = 0;
+    let mut part = Vec::with_capacity(sizes.len());
+
+    for size in sizes.iter() {
+        // try each bin once, starting from the one next to the previously used bin
+        let bin_idx = (0..bins.len()).map(|i| {(cursor + i) % bins.len()}).find(|bin_idx| {
+            bins[*bin_idx].fill(size.clone())
+        }).ok_or("not enough resource".to_string())?;
+        part.push(bin_idx);
+        cursor = bin_idx + 1;
+    }
+
+    Ok((part, bin_ids))
+}
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::hash::Hash;
 
 /// Implementor is used as a bin for storing items.
 pub trait PartitionBin {
@@ -12,6 +13,17 @@ pub trait PartitionBin {
 
     /// Get the id of this bin.
     fn bin_id(&self) -> Self::BinId;
+
+    /// Get the remaining size of this bin.
+    fn remaining(&self) -> Self::Size;
+}
+
+/// Implementor is an item that is stored in a bin.
+pub trait PartitionItem {
+    type Size;
+
+    /// Get the size of this item.
+    fn size(&self) -> Self::Size;
 }
 
 /// Implementor stores multiple items for partition.
@@ -26,4 +38,78 @@ where
     /// 
     /// Return the mapping from the item id to bin id.
     fn partition(&self, bins: I) -> Result<HashMap<Self::ItemId, <T as PartitionBin>::BinId>, String>;
-}
\ No newline at end of file
+}
+
+/// Check the result of a partition.
+///
+/// Every item in `items` must be assigned to exactly one bin in `assignment`,
+/// and all the items assigned to a bin must fit in it. `bins` are the bins in the
+/// state before the partition, which are filled again with the assigned items.
+pub fn check_partition<'a, Id, T, I>(items: I, assignment: &HashMap<Id, T::BinId>, bins: Vec<T>) 
+    -> Result<(), String>
+where
+    Id: 'a + Eq + Hash,
+    T: PartitionBin,
+    T::BinId: Eq + Hash,
+    I: Iterator<Item = (&'a Id, T::Size)>,
+{
+    let mut bins: HashMap<T::BinId, T> = bins.into_iter().map(|bin| {(bin.bin_id(), bin)}).collect();
+    let mut item_count = 0;
+    for (item, size) in items {
+        let bin_id = assignment.get(item).ok_or("an item is not assigned to any bin".to_string())?;
+        let bin = bins.get_mut(bin_id).ok_or("an item is assigned to an unknown bin".to_string())?;
+        if !bin.fill(size) {
+            return Err("a bin is filled beyond its capacity".to_string());
+        }
+        item_count += 1;
+    }
+    if item_count != assignment.len() {
+        return Err("the assignment contains unknown items".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{check_partition, PartitionBin};
+
+    struct Bin(u32, u32); // id and remaining size
+
+    impl PartitionBin for Bin {
+        type Size = u32;
+        type BinId = u32;
+
+        fn fill(&mut self, item_size: u32) -> bool {
+            if self.1 < item_size {
+                return false;
+            }
+            self.1 -= item_size;
+            true
+        }
+
+        fn bin_id(&self) -> u32 {
+            self.0
+        }
+
+        fn remaining(&self) -> u32 {
+            self.1
+        }
+    }
+
+    #[test]
+    fn check_partition_rejects_invalid_assignments() {
+        let items = vec!((1, 2), (2, 2));
+        let check = |assignment: Vec<(u32, u32)>| {
+            let assignment: HashMap<u32, u32> = assignment.into_iter().collect();
+            check_partition(items.iter().map(|(id, size)| {(id, *size)}), &assignment, vec!(Bin(0, 3), Bin(1, 3)))
+        };
+
+        assert_eq!(check(vec!((1, 0), (2, 1))), Ok(()));
+        assert!(check(vec!((1, 0))).is_err()); // unassigned item
+        assert!(check(vec!((1, 0), (2, 2))).is_err()); // unknown bin
+        assert!(check(vec!((1, 0), (2, 0))).is_err()); // overfilled bin
+        assert!(check(vec!((1, 0), (2, 1), (3, 1))).is_err()); // unknown item
+    }
+}
@@ -3,4 +3,8 @@ include!(concat!(env!("OUT_DIR"), "/rpc_proto/capnp/indradb_capnp.rs"));
 
 pub mod hello_world {
     tonic::include_proto!("helloworld");
+}
+
+pub mod container_agent {
+    tonic::include_proto!("container_agent");
 }
\ No newline at end of file
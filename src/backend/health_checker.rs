@@ -0,0 +1,70 @@
+// A background task that periodically checks whether the container servers are reachable.
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::future;
+use tokio::time::{self, timeout, Duration};
+use tower::Service;
+use uuid::Uuid;
+
+use super::conn_service::{ConnService, ServerAddr};
+use crate::database::Client;
+
+// The interval between two rounds of health checks.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+// The time to wait for the connection to a container server.
+const CONN_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Check whether a connection can be made to the server.
+async fn probe(conn_addr: SocketAddr) -> bool {
+    let addr = ServerAddr::new(&conn_addr.ip().to_string(), conn_addr.port()).unwrap();
+    match timeout(CONN_TIMEOUT, ConnService().call(vec!(addr))).await {
+        Ok(Ok(_)) => true,
+        _ => false,
+    }
+}
+
+// Perform a single round of health checks.
+async fn check_once(client: &Client) -> Result<(), String> {
+    let servers = client.list_servers().await.map_err(|e| {format!("{}", e)})??;
+
+    // check all the servers concurrently
+    let results = future::join_all(servers.iter().map(|si| {probe(si.conn_addr())})).await;
+    let health: HashMap<Uuid, bool> = servers.iter().map(|si| {si.id()}).zip(results.into_iter()).collect();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let down: HashSet<Uuid> = client.update_server_health(health, now).await.map_err(|e| {format!("{}", e)})??
+        .into_iter()
+        .collect();
+
+    // flag the emunets whose servers are down, and clear the flag of the recovered ones
+    let emunet_uuids = client.list_all_emu_net_uuids().await.map_err(|e| {format!("{}", e)})??;
+    for uuid in emunet_uuids.into_iter() {
+        // the state is updated atomically, so that the concurrent state transitions are not lost,
+        // the emunet may have been deleted or left unchanged
+        let _ = client.update_emu_net(uuid, |emunet| {
+            if emunet.update_server_health(&down) {
+                Ok(())
+            }
+            else {
+                Err("server health is unchanged".to_string())
+            }
+        }).await.map_err(|e| {format!("{}", e)})?;
+    }
+
+    Ok(())
+}
+
+/// Run the health checker forever, the failed rounds of checks are skipped.
+///
+/// The servers that are down are excluded from the allocation, and the emunets
+/// allocated on them are flagged with `EmuNetState::ServerDown`.
+pub async fn run(client: Client) {
+    let mut interval = time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(err) = check_once(&client).await {
+            log::warn!("health check fails: {}", err);
+        }
+    }
+}
@@ -1,3 +1,4 @@
 pub mod conn_service;
+pub mod health_checker;
 // pub mod resources;
 
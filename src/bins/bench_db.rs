@@ -0,0 +1,104 @@
+// Measure the throughput of the database client under a mixed load:
+// a few tasks keep performing large bulk insertions (as `init_emunet` does),
+// while the other tasks keep reading an emunet (as `get_emunet` does).
+//
+// Usage: bench_db [DB_ADDR], the in-process memory database is used if DB_ADDR is absent.
+// The limits of in-flight requests are only compared against an IndraDB server at DB_ADDR,
+// as the memory database serves each request synchronously and runs a single round.
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tokio::time::{Duration, Instant};
+
+use mocknet::database;
+use mocknet::emunet::server;
+
+const IN_FLIGHT_LIMITS: [usize; 4] = [1, 4, 16, 64];
+const BENCH_DURATION: Duration = Duration::from_secs(5);
+
+const WRITERS: usize = 2;
+const WRITE_BATCH: usize = 2000;
+const READERS: usize = 16;
+
+async fn run_bench(client: database::Client, max_in_flight: usize) -> Result<(), Box<dyn std::error::Error + Send>> {
+    // prepare a user and an emunet to read from
+    let mut sp = server::ServerInfoList::new();
+    sp.add_server_info("127.0.0.1", 128, "128.0.0.2", "129.0.0.5", 1000000, u32::MAX, u32::MAX).unwrap();
+    let _ = client.init(sp.into_vec()).await?;
+
+    let bench_id = indradb::util::generate_uuid_v1();
+    let user = format!("bench-{}", bench_id);
+    client.register_user(&user).await?.unwrap();
+    let emunet_uuid = client.create_emu_net(user.clone(), "bench".to_string(), 1, server::AllocationPolicy::FirstFit).await?.unwrap();
+
+    let reads = Arc::new(AtomicU64::new(0));
+    let writes = Arc::new(AtomicU64::new(0));
+    let deadline = Instant::now() + BENCH_DURATION;
+    let mut handles = Vec::new();
+
+    for _ in 0..WRITERS {
+        let client = client.clone();
+        let writes = writes.clone();
+        let t = user.clone();
+        handles.push(tokio::spawn(async move {
+            while Instant::now() < deadline {
+                let vertexes: Vec<uuid::Uuid> = (0..WRITE_BATCH).map(|_| {indradb::util::generate_uuid_v1()}).collect();
+                client.bulk_create_vertexes(vertexes.iter().cloned(), t.clone()).await.unwrap().unwrap();
+                client.bulk_set_vertex_properties(vertexes.into_iter().map(|uuid| {(uuid, serde_json::Value::Null)})).await.unwrap().unwrap();
+                writes.fetch_add(1, Ordering::Relaxed);
+            }
+        }));
+    }
+
+    for _ in 0..READERS {
+        let client = client.clone();
+        let reads = reads.clone();
+        handles.push(tokio::spawn(async move {
+            while Instant::now() < deadline {
+                client.get_emu_net(emunet_uuid.clone()).await.unwrap().unwrap();
+                reads.fetch_add(1, Ordering::Relaxed);
+            }
+        }));
+    }
+
+    for handle in handles.into_iter() {
+        handle.await.unwrap();
+    }
+
+    let secs = BENCH_DURATION.as_secs_f64();
+    println!(
+        "max_in_flight: {:>3}, get_emunet: {:>10.1} req/s, bulk insertion of {} vertexes: {:>6.1} req/s",
+        max_in_flight,
+        reads.load(Ordering::Relaxed) as f64 / secs,
+        WRITE_BATCH,
+        writes.load(Ordering::Relaxed) as f64 / secs,
+    );
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send>> {
+    let db_addr: Option<SocketAddr> = std::env::args().nth(1).map(|s| {
+        s.parse().expect("could not parse address")
+    });
+
+    // the limit makes no difference to the memory database
+    let limits = match &db_addr {
+        Some(_) => IN_FLIGHT_LIMITS.to_vec(),
+        None => vec!(1),
+    };
+    for max_in_flight in limits.into_iter() {
+        let launcher = match &db_addr {
+            Some(addr) => database::ClientLauncher::connect(addr).await.expect("could not connect to the database"),
+            None => database::ClientLauncher::in_memory(),
+        };
+
+        launcher.max_in_flight(max_in_flight).with_db_client(move |client| {
+            run_bench(client, max_in_flight)
+        }).await?;
+    }
+
+    Ok(())
+}
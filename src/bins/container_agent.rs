@@ -0,0 +1,42 @@
+// The reference container agent that runs on each container server.
+//
+// Usage: container_agent [--listen ADDR] [--docker IMAGE]
+// The agent listens on 0.0.0.0:50051 by default. Without `--docker`, the agent only records
+// the devices and links in memory, which is enough to run `init_emunet` end-to-end locally.
+// With `--docker IMAGE`, each device is launched as a docker container running IMAGE,
+// and the devices are connected with veth pairs.
+use std::net::SocketAddr;
+
+use mocknet::autogen::container_agent::container_agent_server::ContainerAgentServer;
+use mocknet::container_agent::{AgentService, Driver};
+
+const DEFAULT_LISTEN_ADDR: &str = "0.0.0.0:50051";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut listen_addr: SocketAddr = DEFAULT_LISTEN_ADDR.parse().unwrap();
+    let mut driver = Driver::DryRun;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => {
+                let addr = args.next().expect("missing address after --listen");
+                listen_addr = addr.parse().expect("could not parse address");
+            },
+            "--docker" => {
+                let image = args.next().expect("missing image after --docker");
+                driver = Driver::Docker { image };
+            },
+            _ => panic!("unknown argument {}", arg),
+        }
+    }
+
+    println!("container agent listens on {} with driver {:?}", &listen_addr, &driver);
+    tonic::transport::Server::builder()
+        .add_service(ContainerAgentServer::new(AgentService::new(driver)))
+        .serve(listen_addr)
+        .await?;
+
+    Ok(())
+}
@@ -1,100 +1,104 @@
-use std::net::ToSocketAddrs;
 use std::io::{Error, ErrorKind};
 
 use warp::Filter;
 use tokio::time::{timeout, Duration};
 
+use mocknet::config::Config;
 use mocknet::database;
-use mocknet::emunet::server;
+use mocknet::backend::health_checker;
 use mocknet::restful::{*};
 
-const LOCAL_ADDR: [u8; 4] = [127, 0, 0, 1];
-const LOCAL_PORT: u16 = 3030;
-
-const DB_ADDR: [u8; 4] = [127, 0, 0, 1];
-const DB_PORT: u16 = 27615;
-
-use serde::{Serialize, Deserialize};
+use serde::Serialize;
 
 #[derive(Serialize)]
 pub struct ErrorResponse {
     err_reason: String,
 }
 
-#[derive(Deserialize, Serialize)]
-pub struct EdgeInfo {
-    edge_id: (u64, u64), // client side edge id in the form of (u64, u64)
-    description: String, // a description string to hold the place
-}
-
-
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send>> {
-    println!("{}", serde_json::to_string(&EdgeInfo{ description: "wtf".to_string(), edge_id: (1, 2)}).unwrap());
+    // the log level is set by RUST_LOG
+    pretty_env_logger::init();
     
-    // build up the database address
-    let db_addr_str = DB_ADDR.iter().enumerate().fold(String::new(), |mut s, (idx, part)| {
-        if idx < DB_ADDR.len()-1 {
-            s = s + &format!("{}.", part);
-        }
-        else {
-            s = s + &format!("{}:{}", part, DB_PORT);
-        }
-        s
+    // load the configuration and validate the initial server pool
+    let config = Config::load(std::env::args().skip(1)).and_then(|config| {
+        config.server_info_list().map(|sp| {(config, sp)})
     });
-    println!("{}", &db_addr_str);
-    let db_addr = &db_addr_str
-        .to_socket_addrs()
-        .unwrap()
-        .next()
-        .expect("could not parse address");
-
-    // create the database launcher
-    let res = timeout(Duration::from_secs(2), database::ClientLauncher::connect(&db_addr)).await.map_err(|_| {
-        let err_msg: &str = &format!("connection to {} timeout", &db_addr_str);
-        Box::new(Error::new(ErrorKind::Other, err_msg)) as Box<dyn std::error::Error + Send>
-    })?;
-    let launcher = res.map_err(|e| {
-        let err_msg: &str = &format!("connection to {} fails: {}", &db_addr_str, e);
+    let (config, sp) = config.map_err(|err_msg| {
         Box::new(Error::new(ErrorKind::Other, err_msg)) as Box<dyn std::error::Error + Send>
     })?;
+    let listen_addr = config.listen_addr;
+    let reconcile = config.reconcile_servers;
+
+    // create the database launcher, the database runs in-process if `in_memory` is set
+    let launcher = if config.database.in_memory {
+        database::ClientLauncher::in_memory()
+    }
+    else {
+        let db_addr = config.database.addr;
+        let db_timeout = Duration::from_millis(config.database.timeout_ms);
+        let res = timeout(db_timeout, database::ClientLauncher::connect(&db_addr)).await.map_err(|_| {
+            let err_msg: &str = &format!("connection to {} timeout", &db_addr);
+            Box::new(Error::new(ErrorKind::Other, err_msg)) as Box<dyn std::error::Error + Send>
+        })?;
+        res.map_err(|e| {
+            let err_msg: &str = &format!("connection to {} fails: {}", &db_addr, e);
+            Box::new(Error::new(ErrorKind::Other, err_msg)) as Box<dyn std::error::Error + Send>
+        })?
+    };
     
-    launcher.with_db_client(|client| {
+    let servers = sp.into_vec();
+    launcher.with_db_client(move |client| {
+        let servers = servers.clone();
         async move {
-            // an initial server pool for testing purpose, the server pool should 
-            // be initialized from program inputs
-            let mut sp = server::ServerInfoList::new(); 
-            sp.add_server_info("127.0.0.1", 128, "128.0.0.2", "129.0.0.5", 5).unwrap();
-            sp.add_server_info("127.0.0.2", 128, "128.0.0.3", "129.0.0.4", 7).unwrap();
-            sp.add_server_info("137.0.0.1", 128, "138.0.0.2", "139.0.0.5", 9).unwrap();
-            sp.add_server_info("137.0.0.2", 128, "138.0.0.3", "139.0.0.4", 10).unwrap();
-            
-            // try to initialize the database
-            let res = client.init(sp.into_vec()).await?;
+            // try to initialize the database with the initial server pool
+            let res = client.init(servers.clone()).await?;
             match res {
                 Ok(_) => {
-                    println!("successfully initialize the database")
+                    log::info!("successfully initialize the database")
                 },
                 Err(s) => {
-                    // the database has been initialized, just print the error message
-                    println!("{}", &s);
+                    // the database has been initialized, just log the error message
+                    log::info!("{}", &s);
+
+                    if reconcile {
+                        // apply the configured server pool to the initialized database, 
+                        // the server does not start with a server pool that is refused
+                        match client.reconcile_servers(servers).await? {
+                            Ok(report) => log::info!("server pool reconciled: {}", serde_json::to_string(&report).unwrap()),
+                            Err(s) => {
+                                log::error!("fail to reconcile the server pool: {}", &s);
+                                let err_msg: &str = &format!("fail to reconcile the server pool: {}", &s);
+                                return Err(Box::new(Error::new(ErrorKind::Other, err_msg)) as Box<dyn std::error::Error + Send>);
+                            },
+                        };
+                    }
                 }
             };
             
+            // periodically check the health of the container servers in the background
+            tokio::spawn(health_checker::run(client.clone()));
+
             // build up the warp filters
             let ru = register_user::build_filter(client.clone());
             let ce = create_emunet::build_filter(client.clone());
             let le = list_emunet::build_filter(client.clone());
             let ge = get_emunet::build_filter(client.clone());
             let ie = init_emunet::build_filter(client.clone());
-            let routes = ru.or(ce).or(le).or(ge).or(ie);
+            let ue = update_emunet::build_filter(client.clone());
+            let de = delete_emunet::build_filter(client.clone());
+            let sa = add_server::build_filter(client.clone());
+            let sl = list_servers::build_filter(client.clone());
+            let sd = drain_server::build_filter(client.clone());
+            let sr = remove_server::build_filter(client.clone());
+            let routes = ru.or(ce).or(le).or(ge).or(ie).or(ue).or(de).or(sa).or(sl).or(sd).or(sr);
 
             // launch the warp server
-            warp::serve(routes).run((LOCAL_ADDR, LOCAL_PORT)).await; 
+            warp::serve(routes).run(listen_addr).await; 
 
             Ok(())
         }
-    }).await.unwrap();
+    }).await?;
     
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,314 @@
+// The configuration of the mocknet server.
+//
+// The configuration is read from a JSON file, then overridden by the environment
+// variables, and finally overridden by the command line flags:
+//
+// | file                   | environment variable    | flag                |
+// |------------------------|-------------------------|---------------------|
+// | listen_addr            | MOCKNET_LISTEN_ADDR     | --listen ADDR       |
+// | database.in_memory     | MOCKNET_IN_MEMORY       | --in-memory         |
+// | database.addr          | MOCKNET_DB_ADDR         | --db ADDR           |
+// | database.timeout_ms    | MOCKNET_DB_TIMEOUT_MS   | --db-timeout MS     |
+// | reconcile_servers      | MOCKNET_RECONCILE       | --reconcile         |
+//
+// The path of the file is given by `--config PATH` or MOCKNET_CONFIG, otherwise
+// config/mocknet.json is read if it exists. Without a file, the default configuration
+// is used, which has an empty server pool.
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::emunet::server::ServerInfoList;
+
+// the config file read when no path is given
+const DEFAULT_CONFIG_PATH: &str = "config/mocknet.json";
+
+fn default_listen_addr() -> SocketAddr {
+    "127.0.0.1:3030".parse().unwrap()
+}
+
+fn default_db_addr() -> SocketAddr {
+    "127.0.0.1:27615".parse().unwrap()
+}
+
+fn default_db_timeout_ms() -> u64 {
+    2000
+}
+
+// cpu and memory are not limited if they are not specified
+fn unlimited() -> u32 {
+    u32::MAX
+}
+
+/// The configuration of the database.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// Use the in-process memory database instead of connecting to a database server.
+    #[serde(default)]
+    pub in_memory: bool,
+    /// The address of the database server.
+    #[serde(default = "default_db_addr")]
+    pub addr: SocketAddr,
+    /// The time to wait for the connection to the database server.
+    #[serde(default = "default_db_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            in_memory: false,
+            addr: default_db_addr(),
+            timeout_ms: default_db_timeout_ms(),
+        }
+    }
+}
+
+/// A container server in the initial server pool.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub conn_ip: String,
+    pub conn_port: u16,
+    pub data_ip: String,
+    pub man_ip: String,
+    pub max_capacity: u32,
+    #[serde(default = "unlimited")]
+    pub max_cpu: u32,
+    #[serde(default = "unlimited")]
+    pub max_memory: u32,
+}
+
+/// The configuration of the mocknet server.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The address that the RESTful API listens on.
+    #[serde(default = "default_listen_addr")]
+    pub listen_addr: SocketAddr,
+    #[serde(default)]
+    pub database: DatabaseConfig,
+    /// The initial server pool, which is stored when the database is initialized.
+    #[serde(default)]
+    pub servers: Vec<ServerConfig>,
+    /// Reconcile the server pool stored in an initialized database with `servers`.
+    #[serde(default)]
+    pub reconcile_servers: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen_addr: default_listen_addr(),
+            database: DatabaseConfig::default(),
+            servers: Vec::new(),
+            reconcile_servers: false,
+        }
+    }
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| {format!("invalid value {} for {}", value, name)})
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        _ => Err(format!("invalid value {} for {}", value, name)),
+    }
+}
+
+impl Config {
+    /// Read the configuration from a JSON file.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            format!("fail to read config file {}: {}", path, e)
+        })?;
+        serde_json::from_str(&content).map_err(|e| {
+            format!("invalid config file {}: {}", path, e)
+        })
+    }
+
+    /// Load the configuration from the config file, the environment variables
+    /// and the command line arguments `args` (excluding the program name).
+    pub fn load<I: Iterator<Item = String>>(args: I) -> Result<Self, String> {
+        Self::load_with_env(args, |name| {std::env::var(name).ok()})
+    }
+
+    // `load` with the environment variables looked up by `env`
+    fn load_with_env<I, E>(args: I, env: E) -> Result<Self, String> 
+    where
+        I: Iterator<Item = String>,
+        E: Fn(&str) -> Option<String>,
+    {
+        let args: Vec<String> = args.collect();
+
+        // find the config file
+        let mut path = env("MOCKNET_CONFIG");
+        if let Some(idx) = args.iter().position(|arg| {arg == "--config"}) {
+            path = Some(args.get(idx + 1).ok_or("missing path after --config".to_string())?.clone());
+        }
+        if path.is_none() && std::path::Path::new(DEFAULT_CONFIG_PATH).exists() {
+            path = Some(DEFAULT_CONFIG_PATH.to_string());
+        }
+        let mut config = match path {
+            Some(path) => Self::from_file(&path)?,
+            None => Self::default(),
+        };
+
+        // override with the environment variables
+        if let Some(v) = env("MOCKNET_LISTEN_ADDR") {
+            config.listen_addr = parse("MOCKNET_LISTEN_ADDR", &v)?;
+        }
+        if let Some(v) = env("MOCKNET_IN_MEMORY") {
+            config.database.in_memory = parse_bool("MOCKNET_IN_MEMORY", &v)?;
+        }
+        if let Some(v) = env("MOCKNET_DB_ADDR") {
+            config.database.addr = parse("MOCKNET_DB_ADDR", &v)?;
+        }
+        if let Some(v) = env("MOCKNET_DB_TIMEOUT_MS") {
+            config.database.timeout_ms = parse("MOCKNET_DB_TIMEOUT_MS", &v)?;
+        }
+        if let Some(v) = env("MOCKNET_RECONCILE") {
+            config.reconcile_servers = parse_bool("MOCKNET_RECONCILE", &v)?;
+        }
+
+        // override with the command line flags, there are only a few flags and each of them 
+        // sets a single field, so they are parsed here without a CLI parser
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {args.next().ok_or(format!("missing value after {}", &arg))};
+            match arg.as_str() {
+                "--config" => {
+                    let _ = value()?;
+                },
+                "--listen" => config.listen_addr = parse("--listen", &value()?)?,
+                "--in-memory" => config.database.in_memory = true,
+                "--db" => config.database.addr = parse("--db", &value()?)?,
+                "--db-timeout" => config.database.timeout_ms = parse("--db-timeout", &value()?)?,
+                "--reconcile" => config.reconcile_servers = true,
+                _ => return Err(format!("unknown argument {}", &arg)),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Build up the initial server pool, report the first invalid server.
+    pub fn server_info_list(&self) -> Result<ServerInfoList, String> {
+        let mut sp = ServerInfoList::new();
+        for (idx, sc) in self.servers.iter().enumerate() {
+            sp.add_server_info(
+                &sc.conn_ip, sc.conn_port, &sc.data_ip, &sc.man_ip,
+                sc.max_capacity, sc.max_cpu, sc.max_memory
+            ).map_err(|e| {
+                format!("invalid server servers[{}]: {}", idx, e)
+            })?;
+        }
+        Ok(sp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    // write `content` into a new config file and return its path
+    fn config_file(content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("mocknet-{}.json", indradb::util::generate_uuid_v1()));
+        std::fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn load(args: Vec<&str>, env: Vec<(&str, &str)>) -> Result<Config, String> {
+        let env: HashMap<String, String> = env.into_iter().map(|(k, v)| {(k.to_string(), v.to_string())}).collect();
+        Config::load_with_env(args.into_iter().map(|arg| {arg.to_string()}), |name| {env.get(name).cloned()})
+    }
+
+    #[test]
+    fn flags_override_env_which_overrides_file() {
+        let path = config_file(r#"{
+            "listen_addr": "10.0.0.1:1000",
+            "database": {"addr": "10.0.0.1:2000", "timeout_ms": 100}
+        }"#);
+        let path = path.as_str();
+        let env = vec!(("MOCKNET_LISTEN_ADDR", "10.0.0.2:1000"), ("MOCKNET_DB_ADDR", "10.0.0.2:2000"), ("MOCKNET_RECONCILE", "1"));
+        let config = load(vec!("--config", path, "--listen", "10.0.0.3:1000", "--in-memory"), env).unwrap();
+        assert_eq!(config.listen_addr, "10.0.0.3:1000".parse().unwrap());
+        assert_eq!(config.database.addr, "10.0.0.2:2000".parse().unwrap());
+        assert_eq!(config.database.timeout_ms, 100);
+        assert!(config.database.in_memory);
+        assert!(config.reconcile_servers);
+
+        // the file given by the flag is preferred over the one in the environment
+        let config = load(vec!("--config", path), vec!(("MOCKNET_CONFIG", "/nonexistent.json"))).unwrap();
+        assert_eq!(config.listen_addr, "10.0.0.1:1000".parse().unwrap());
+        let config = load(vec!(), vec!(("MOCKNET_CONFIG", path), ("MOCKNET_DB_TIMEOUT_MS", "300"))).unwrap();
+        assert_eq!(config.listen_addr, "10.0.0.1:1000".parse().unwrap());
+        assert_eq!(config.database.timeout_ms, 300);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn invalid_settings_are_reported() {
+        let path = config_file("{}");
+        let path = path.as_str();
+        let cases = vec!(
+            (vec!("--config", path, "--verbose"), vec!(), "unknown argument --verbose"),
+            (vec!("--config", path, "--db"), vec!(), "missing value after --db"),
+            (vec!("--config", path, "--db-timeout", "soon"), vec!(), "invalid value soon for --db-timeout"),
+            (vec!("--config", path), vec!(("MOCKNET_IN_MEMORY", "yes")), "invalid value yes for MOCKNET_IN_MEMORY"),
+            (vec!("--config"), vec!(), "missing path after --config"),
+        );
+        for (args, env, err) in cases.into_iter() {
+            assert_eq!(load(args, env).unwrap_err(), err);
+        }
+        std::fs::remove_file(path).unwrap();
+
+        let path = config_file(r#"{"listen": "10.0.0.1:1000"}"#);
+        let path = path.as_str();
+        assert!(load(vec!("--config", path), vec!()).unwrap_err().starts_with(&format!("invalid config file {}", path)));
+        std::fs::remove_file(path).unwrap();
+        assert!(load(vec!("--config", path), vec!()).unwrap_err().starts_with(&format!("fail to read config file {}", path)));
+    }
+
+    fn server(conn_ip: &str, data_ip: &str, man_ip: &str, max_capacity: u32) -> ServerConfig {
+        ServerConfig {
+            conn_ip: conn_ip.to_string(),
+            conn_port: 50051,
+            data_ip: data_ip.to_string(),
+            man_ip: man_ip.to_string(),
+            max_capacity,
+            max_cpu: unlimited(),
+            max_memory: unlimited(),
+        }
+    }
+
+    #[test]
+    fn first_invalid_server_is_reported() {
+        let mut config = Config::default();
+        config.servers = vec!(server("127.0.0.1", "10.0.0.1", "10.1.0.1", 5), server("127.0.0.2", "10.0.0.2", "10.1.0.2", 5));
+        assert_eq!(config.server_info_list().unwrap().into_vec().len(), 2);
+
+        let cases = vec!(
+            (server("127.0.0.3", "10.0.0.3", "10.1.0.3", 0), "invalid server servers[2]: the maximum capacity of the server is zero"),
+            (server("127.0.0.1", "10.0.0.3", "10.1.0.3", 5), "invalid server servers[2]: connection address 127.0.0.1:50051 is used by another server"),
+            (server("127.0.0.3", "10.0.0.1", "10.1.0.3", 5), "invalid server servers[2]: data IP 10.0.0.1 is used by another server"),
+            (server("127.0.0.3", "10.0.0.3", "10.1.0.2", 5), "invalid server servers[2]: management IP 10.1.0.2 is used by another server"),
+        );
+        for (sc, err) in cases.into_iter() {
+            config.servers.truncate(2);
+            config.servers.push(sc);
+            assert_eq!(config.server_info_list().unwrap_err(), err);
+        }
+
+        config.servers.truncate(2);
+        config.servers.push(server("localhost", "10.0.0.3", "10.1.0.3", 5));
+        assert!(config.server_info_list().unwrap_err().starts_with("invalid server servers[2]: invalid IP address"));
+    }
+}
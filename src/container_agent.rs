@@ -0,0 +1,470 @@
+// The reference implementation of the container agent service that runs on each container server.
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use tonic::{Request, Response, Status};
+
+use crate::autogen::container_agent::container_agent_server::ContainerAgent;
+use crate::autogen::container_agent::{Device, Link, OpResult, DeviceStatus};
+use crate::autogen::container_agent::{CreateDevicesRequest, CreateDevicesReply};
+use crate::autogen::container_agent::{CreateLinksRequest, CreateLinksReply};
+use crate::autogen::container_agent::{DeleteLinksRequest, DeleteLinksReply};
+use crate::autogen::container_agent::{DeleteDevicesRequest, DeleteDevicesReply};
+use crate::autogen::container_agent::{StatusRequest, StatusReply};
+
+/// The driver that actually creates the devices and links on the server.
+#[derive(Clone, Debug)]
+pub enum Driver {
+    /// Only record the devices and links in memory.
+    DryRun,
+    /// Launch each device as a docker container running `image`,
+    /// and connect the containers with veth pairs, or VXLAN tunnels across servers.
+    Docker { image: String },
+}
+
+// The UDP port of the VXLAN tunnels between the servers.
+const VXLAN_PORT: &str = "4789";
+
+// A device created on this server.
+struct DeviceRecord {
+    container: String, // name of the container
+    used_ports: BTreeSet<u32>, // indexes of the interfaces attached to links
+    total_ports: u32, // zero means unlimited
+}
+
+// One end of a link, the device uuid and the interface index.
+type LinkEnd = (String, u32);
+
+// All the devices and links of an emunet created on this server.
+#[derive(Default)]
+struct EmuNetRecord {
+    devices: HashMap<String, DeviceRecord>,
+    links: HashSet<(LinkEnd, LinkEnd)>, // the two ends of each link, in ascending order
+}
+
+// The key of the link record, which does not depend on the direction of the link.
+// The interfaces tell apart the parallel links between the same devices.
+fn link_key(link: &Link, ports: (u32, u32)) -> (LinkEnd, LinkEnd) {
+    let end0 = (link.uuid0.clone(), ports.0);
+    let end1 = (link.uuid1.clone(), ports.1);
+    if end0 < end1 { (end0, end1) } else { (end1, end0) }
+}
+
+// The interfaces of the link on its two devices, either given by the link or the free 
+// interfaces with the smallest indexes on the local devices.
+fn link_ports(emunet: &EmuNetRecord, link: &Link) -> (u32, u32) {
+    if link.has_ports {
+        return (link.port0, link.port1);
+    }
+    let free_port = |uuid: &String, taken: Option<u32>| {
+        emunet.devices.get(uuid).map(|dev| {
+            (0..).find(|port| {!dev.used_ports.contains(port) && Some(*port) != taken}).unwrap()
+        }).unwrap_or(0)
+    };
+    let port0 = free_port(&link.uuid0, None);
+    let port1 = free_port(&link.uuid1, if link.uuid0 == link.uuid1 { Some(port0) } else { None });
+    (port0, port1)
+}
+
+// Find the recorded link, the interfaces are only compared if they are given by the link.
+fn find_link(emunet: &EmuNetRecord, link: &Link) -> Option<(LinkEnd, LinkEnd)> {
+    if link.has_ports {
+        let key = link_key(link, (link.port0, link.port1));
+        return if emunet.links.contains(&key) { Some(key) } else { None };
+    }
+    let uuids = if link.uuid0 < link.uuid1 { (&link.uuid0, &link.uuid1) } else { (&link.uuid1, &link.uuid0) };
+    emunet.links.iter().find(|(end0, end1)| {(&end0.0, &end1.0) == uuids}).cloned()
+}
+
+#[derive(Default)]
+struct AgentState {
+    emunets: HashMap<String, EmuNetRecord>,
+    veth_counter: u64, // used to generate unique names for the veth pairs
+}
+
+/// The container agent service.
+///
+/// All the operations are serialized, and the created devices and links are tracked in memory.
+pub struct AgentService {
+    driver: Driver,
+    state: Mutex<AgentState>,
+}
+
+impl AgentService {
+    pub fn new(driver: Driver) -> Self {
+        Self {
+            driver,
+            state: Mutex::new(AgentState::default()),
+        }
+    }
+}
+
+fn op_result(uuids: Vec<String>, res: Result<(), String>) -> OpResult {
+    match res {
+        Ok(_) => OpResult { uuids, success: true, message: String::new() },
+        Err(message) => OpResult { uuids, success: false, message },
+    }
+}
+
+// Run a command, return the trimmed stdout on success and an error message on failure.
+async fn run_cmd(program: &str, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(program).args(args).output().await.map_err(|e| {
+        format!("fail to run {}: {}", program, e)
+    })?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+    else {
+        Err(format!("{} {} fails: {}", program, args.join(" "), String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+// Move one end of a veth pair or a VXLAN device into the network namespace of a container,
+// and rename it to `eth{port}`.
+async fn attach_veth(veth: &str, container: &str, port: u32) -> Result<(), String> {
+    let pid = run_cmd("docker", &["inspect", "-f", "{{.State.Pid}}", container]).await?;
+    let port_name = format!("eth{}", port);
+    run_cmd("ip", &["link", "set", veth, "netns", &pid]).await?;
+    run_cmd("nsenter", &["-t", &pid, "-n", "ip", "link", "set", "dev", veth, "name", &port_name]).await?;
+    run_cmd("nsenter", &["-t", &pid, "-n", "ip", "link", "set", "dev", &port_name, "up"]).await?;
+    Ok(())
+}
+
+// Remove the interface `eth{port}` from a container, removing one end of a veth pair
+// removes the other end as well.
+async fn delete_interface(container: &str, port: u32) -> Result<(), String> {
+    let pid = run_cmd("docker", &["inspect", "-f", "{{.State.Pid}}", container]).await?;
+    run_cmd("nsenter", &["-t", &pid, "-n", "ip", "link", "del", &format!("eth{}", port)]).await?;
+    Ok(())
+}
+
+// Apply the traffic control settings of the link to the interface `eth{port}` of a container.
+async fn apply_link_attrs(container: &str, port: u32, link: &Link) -> Result<(), String> {
+    let pid = run_cmd("docker", &["inspect", "-f", "{{.State.Pid}}", container]).await?;
+    let port_name = format!("eth{}", port);
+    if link.mtu > 0 {
+        run_cmd("nsenter", &["-t", &pid, "-n", "ip", "link", "set", "dev", &port_name, "mtu", &link.mtu.to_string()]).await?;
+    }
+
+    // all the other settings are implemented by a single netem qdisc
+    let mut netem: Vec<String> = Vec::new();
+    if link.delay_us > 0 {
+        netem.extend(vec!("delay".to_string(), format!("{}us", link.delay_us)));
+        if link.jitter_us > 0 {
+            netem.push(format!("{}us", link.jitter_us));
+        }
+    }
+    if link.loss_percent > 0.0 {
+        netem.extend(vec!("loss".to_string(), format!("{}%", link.loss_percent)));
+    }
+    if link.rate_kbps > 0 {
+        netem.extend(vec!("rate".to_string(), format!("{}kbit", link.rate_kbps)));
+    }
+    if link.queue_size > 0 {
+        netem.extend(vec!("limit".to_string(), link.queue_size.to_string()));
+    }
+    if !netem.is_empty() {
+        let mut args = vec!("-t", pid.as_str(), "-n", "tc", "qdisc", "add", "dev", port_name.as_str(), "root", "netem");
+        args.extend(netem.iter().map(|s| {s.as_str()}));
+        run_cmd("nsenter", &args).await?;
+    }
+    Ok(())
+}
+
+impl AgentService {
+    async fn create_device(&self, emunet: &mut EmuNetRecord, emunet_name: &str, dev: Device) -> Result<(), String> {
+        if emunet.devices.contains_key(&dev.uuid) {
+            return Err(format!("device {} already exists", &dev.uuid));
+        }
+
+        let container = format!("{}-{}", emunet_name, dev.id);
+        if let Driver::Docker { image } = &self.driver {
+            let mut args: Vec<String> = vec!("run", "-d", "--network", "none", "--name", container.as_str())
+                .into_iter().map(|s| {s.to_string()}).collect();
+            args.extend(vec!("--label".to_string(), format!("mocknet.role={}", &dev.role)));
+            if dev.role == "router" {
+                args.extend(vec!("--sysctl".to_string(), "net.ipv4.ip_forward=1".to_string()));
+            }
+            if dev.cpu > 0 {
+                args.extend(vec!("--cpus".to_string(), format!("{}", dev.cpu as f64 / 1000.0)));
+            }
+            if dev.memory > 0 {
+                args.extend(vec!("--memory".to_string(), format!("{}m", dev.memory)));
+            }
+            for (name, value) in dev.env.iter() {
+                args.extend(vec!("-e".to_string(), format!("{}={}", name, value)));
+            }
+
+            // the default image is kept alive by sleeping, unless a command is given
+            if dev.image.is_empty() {
+                args.push(image.clone());
+                if dev.command.is_empty() {
+                    args.extend(vec!("sleep".to_string(), "infinity".to_string()));
+                }
+            }
+            else {
+                args.push(dev.image.clone());
+            }
+            args.extend(dev.command.iter().cloned());
+
+            let args: Vec<&str> = args.iter().map(|s| {s.as_str()}).collect();
+            run_cmd("docker", &args).await?;
+        }
+
+        emunet.devices.insert(dev.uuid, DeviceRecord {
+            container,
+            used_ports: BTreeSet::new(),
+            total_ports: dev.total_ports,
+        });
+        Ok(())
+    }
+
+    async fn create_link(&self, emunet: &mut EmuNetRecord, veth_counter: &mut u64, link: Link) -> Result<(), String> {
+        let ports = link_ports(emunet, &link);
+        let key = link_key(&link, ports);
+        if emunet.links.contains(&key) {
+            return Err("link already exists".to_string());
+        }
+
+        // the interfaces must be free, and within the interfaces of the devices
+        if link.uuid0 == link.uuid1 && ports.0 == ports.1 {
+            return Err(format!("both ends of the self loop of device {} use interface {}", &link.uuid0, ports.0));
+        }
+        for (uuid, port) in vec!((&link.uuid0, ports.0), (&link.uuid1, ports.1)).into_iter() {
+            if let Some(dev) = emunet.devices.get(uuid) {
+                if dev.total_ports > 0 && port >= dev.total_ports {
+                    return Err(format!("device {} has no interface {}", uuid, port));
+                }
+                if dev.used_ports.contains(&port) {
+                    return Err(format!("interface {} of device {} is in use", port, uuid));
+                }
+            }
+        }
+
+        let local_ends = (emunet.devices.contains_key(&link.uuid0), emunet.devices.contains_key(&link.uuid1));
+        match (local_ends, &self.driver) {
+            ((false, false), _) => {
+                return Err("none of the devices of the link is on this server".to_string());
+            },
+            ((true, true), Driver::Docker { .. }) => {
+                // connect the two containers with a veth pair
+                let veth0 = format!("mnveth{}a", veth_counter);
+                let veth1 = format!("mnveth{}b", veth_counter);
+                *veth_counter += 1;
+                run_cmd("ip", &["link", "add", &veth0, "type", "veth", "peer", "name", &veth1]).await?;
+
+                let dev0 = emunet.devices.get(&link.uuid0).unwrap();
+                let dev1 = emunet.devices.get(&link.uuid1).unwrap();
+                let attached = async {
+                    attach_veth(&veth0, &dev0.container, ports.0).await?;
+                    attach_veth(&veth1, &dev1.container, ports.1).await
+                }.await;
+                if let Err(err) = attached {
+                    // remove the end left on the host, which removes the other end as well
+                    for veth in vec!(&veth0, &veth1).into_iter() {
+                        let _ = run_cmd("ip", &["link", "del", veth]).await;
+                    }
+                    return Err(err);
+                }
+
+                // shape the traffic on both ends of the veth pair, the pair is removed on failure
+                // so that the interfaces can be used again
+                let shaped = async {
+                    apply_link_attrs(&dev0.container, ports.0, &link).await?;
+                    apply_link_attrs(&dev1.container, ports.1, &link).await
+                }.await;
+                if let Err(err) = shaped {
+                    let _ = delete_interface(&dev0.container, ports.0).await;
+                    return Err(err);
+                }
+            },
+            (_, Driver::Docker { .. }) if link.vxlan => {
+                // connect the local container to the remote server with a VXLAN tunnel
+                let (uuid, port, local_ip, remote_ip) = if local_ends.0 {
+                    (&link.uuid0, ports.0, &link.data_ip0, &link.data_ip1)
+                }
+                else {
+                    (&link.uuid1, ports.1, &link.data_ip1, &link.data_ip0)
+                };
+                let vxlan = format!("mnvx{}", link.vni);
+                let vni = link.vni.to_string();
+                run_cmd("ip", &["link", "add", &vxlan, "type", "vxlan", "id", &vni, "local", local_ip, "remote", remote_ip, "dstport", VXLAN_PORT]).await?;
+
+                let dev = emunet.devices.get(uuid).unwrap();
+                if let Err(err) = attach_veth(&vxlan, &dev.container, port).await {
+                    let _ = run_cmd("ip", &["link", "del", &vxlan]).await;
+                    return Err(err);
+                }
+                if let Err(err) = apply_link_attrs(&dev.container, port, &link).await {
+                    let _ = delete_interface(&dev.container, port).await;
+                    return Err(err);
+                }
+            },
+            (_, Driver::Docker { .. }) => {
+                return Err("links across container servers require a VXLAN tunnel".to_string());
+            },
+            (_, Driver::DryRun) => {},
+        };
+
+        // update the interfaces of the local devices
+        for (uuid, port) in vec!(&key.0, &key.1).into_iter() {
+            if let Some(dev) = emunet.devices.get_mut(uuid) {
+                dev.used_ports.insert(*port);
+            }
+        }
+        emunet.links.insert(key);
+        Ok(())
+    }
+
+    async fn delete_link(&self, emunet: &mut EmuNetRecord, link: Link) -> Result<(), String> {
+        let key = match find_link(emunet, &link) {
+            Some(key) => key,
+            None => return Err("link does not exist".to_string()),
+        };
+
+        // removing one end of a veth pair removes the other end as well
+        let local_end = vec!(&key.0, &key.1).into_iter().find(|(uuid, _)| {emunet.devices.contains_key(uuid)});
+        if let (Driver::Docker { .. }, Some((uuid, port))) = (&self.driver, local_end) {
+            delete_interface(&emunet.devices.get(uuid).unwrap().container, *port).await?;
+        }
+
+        for (uuid, port) in vec!(&key.0, &key.1).into_iter() {
+            if let Some(dev) = emunet.devices.get_mut(uuid) {
+                dev.used_ports.remove(port);
+            }
+        }
+        emunet.links.remove(&key);
+        Ok(())
+    }
+
+    async fn delete_device(&self, emunet: &mut EmuNetRecord, uuid: &str) -> Result<(), String> {
+        let dev = match emunet.devices.get(uuid) {
+            Some(dev) => dev,
+            None => return Err(format!("device {} does not exist", uuid)),
+        };
+
+        // the veth ends inside the container are removed together with the container
+        if let Driver::Docker { .. } = &self.driver {
+            run_cmd("docker", &["rm", "-f", &dev.container]).await?;
+        }
+
+        emunet.devices.remove(uuid);
+        // the interfaces of the peer devices are freed as well
+        let dropped: Vec<(LinkEnd, LinkEnd)> = emunet.links.iter().filter(|(end0, end1)| {end0.0 == uuid || end1.0 == uuid}).cloned().collect();
+        for key in dropped.into_iter() {
+            for (peer, port) in vec!(&key.0, &key.1).into_iter() {
+                if let Some(dev) = emunet.devices.get_mut(peer) {
+                    dev.used_ports.remove(port);
+                }
+            }
+            emunet.links.remove(&key);
+        }
+        Ok(())
+    }
+
+    async fn device_running(&self, dev: &DeviceRecord) -> bool {
+        match &self.driver {
+            Driver::DryRun => true,
+            Driver::Docker { .. } => {
+                let res = run_cmd("docker", &["inspect", "-f", "{{.State.Running}}", &dev.container]).await;
+                res.map(|s| {s == "true"}).unwrap_or(false)
+            }
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl ContainerAgent for AgentService {
+    async fn create_devices(&self, request: Request<CreateDevicesRequest>) -> Result<Response<CreateDevicesReply>, Status> {
+        let req = request.into_inner();
+        let mut state = self.state.lock().await;
+        let emunet = state.emunets.entry(req.emunet.clone()).or_insert_with(EmuNetRecord::default);
+
+        let mut results = Vec::new();
+        for dev in req.devices.into_iter() {
+            let uuids = vec!(dev.uuid.clone());
+            let res = self.create_device(emunet, &req.emunet, dev).await;
+            results.push(op_result(uuids, res));
+        }
+
+        Ok(Response::new(CreateDevicesReply { results }))
+    }
+
+    async fn create_links(&self, request: Request<CreateLinksRequest>) -> Result<Response<CreateLinksReply>, Status> {
+        let req = request.into_inner();
+        let mut state = self.state.lock().await;
+        let state = &mut *state;
+        let emunet = match state.emunets.get_mut(&req.emunet) {
+            Some(emunet) => emunet,
+            None => return Err(Status::not_found(format!("emunet {} does not exist", &req.emunet))),
+        };
+
+        let mut results = Vec::new();
+        for link in req.links.into_iter() {
+            let uuids = vec!(link.uuid0.clone(), link.uuid1.clone());
+            let res = self.create_link(emunet, &mut state.veth_counter, link).await;
+            results.push(op_result(uuids, res));
+        }
+
+        Ok(Response::new(CreateLinksReply { results }))
+    }
+
+    async fn delete_links(&self, request: Request<DeleteLinksRequest>) -> Result<Response<DeleteLinksReply>, Status> {
+        let req = request.into_inner();
+        let mut state = self.state.lock().await;
+        let emunet = match state.emunets.get_mut(&req.emunet) {
+            Some(emunet) => emunet,
+            None => return Err(Status::not_found(format!("emunet {} does not exist", &req.emunet))),
+        };
+
+        let mut results = Vec::new();
+        for link in req.links.into_iter() {
+            let uuids = vec!(link.uuid0.clone(), link.uuid1.clone());
+            let res = self.delete_link(emunet, link).await;
+            results.push(op_result(uuids, res));
+        }
+
+        Ok(Response::new(DeleteLinksReply { results }))
+    }
+
+    async fn delete_devices(&self, request: Request<DeleteDevicesRequest>) -> Result<Response<DeleteDevicesReply>, Status> {
+        let req = request.into_inner();
+        let mut state = self.state.lock().await;
+        let emunet = match state.emunets.get_mut(&req.emunet) {
+            Some(emunet) => emunet,
+            None => return Err(Status::not_found(format!("emunet {} does not exist", &req.emunet))),
+        };
+
+        let mut results = Vec::new();
+        for uuid in req.uuids.into_iter() {
+            let res = self.delete_device(emunet, &uuid).await;
+            results.push(op_result(vec!(uuid), res));
+        }
+        if emunet.devices.is_empty() {
+            state.emunets.remove(&req.emunet);
+        }
+
+        Ok(Response::new(DeleteDevicesReply { results }))
+    }
+
+    async fn status(&self, request: Request<StatusRequest>) -> Result<Response<StatusReply>, Status> {
+        let req = request.into_inner();
+        let state = self.state.lock().await;
+        let emunet = match state.emunets.get(&req.emunet) {
+            Some(emunet) => emunet,
+            None => return Err(Status::not_found(format!("emunet {} does not exist", &req.emunet))),
+        };
+
+        let mut devices = Vec::new();
+        for (uuid, dev) in emunet.devices.iter() {
+            devices.push(DeviceStatus {
+                uuid: uuid.clone(),
+                running: self.device_running(dev).await,
+                link_count: dev.used_ports.len() as u32,
+            });
+        }
+
+        Ok(Response::new(StatusReply { devices }))
+    }
+}
@@ -1,43 +1,202 @@
-pub mod hello_world {
-    tonic::include_proto!("helloworld");
-}
+use std::collections::HashMap;
+use std::net::SocketAddr;
 
-use std::iter::Iterator;
+use tokio::time::{timeout, Duration};
+use uuid::Uuid;
 
-use hello_world::greeter_client::GreeterClient;
-use hello_world::HelloRequest;
+use crate::autogen::container_agent::container_agent_client::ContainerAgentClient;
+use crate::autogen::container_agent::{Device, Link, OpResult};
+use crate::autogen::container_agent::{CreateDevicesRequest, CreateLinksRequest};
+use crate::autogen::container_agent::{DeleteDevicesRequest, DeleteLinksRequest};
+use crate::emunet::net::{Vertex, Edge, DeviceState};
+use crate::emunet::link_plan::LinkRealization;
 
-pub struct ContainerServerAddr(String);
+// The time to wait for the connection to a container server.
+const CONN_TIMEOUT: Duration = Duration::from_secs(5);
 
-pub struct ContainerBackendBuilder<I> { 
-    addr_iter: I,
+/// The devices and links that are assigned to a single container server.
+pub struct ServerAssignment {
+    conn_addr: SocketAddr,
+    devices: Vec<Device>,
+    links: Vec<Link>,
 }
 
-impl<I> ContainerBackendBuilder<I>
-    where I: Iterator<Item = ContainerServerAddr> 
-{
-    pub fn new(iter: I) -> Self {
+impl ServerAssignment {
+    pub fn new(conn_addr: SocketAddr) -> Self {
         Self {
-            addr_iter: iter,
+            conn_addr,
+            devices: Vec::new(),
+            links: Vec::new(),
         }
     }
 
-    pub async fn build(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        for addr in &mut self.addr_iter {
-            let mut server_client = GreeterClient::connect(addr.0).await?;
+    pub fn add_device(&mut self, vertex: &Vertex) {
+        let vi = vertex.vertex_info();
+        let resources = vi.resources();
+        self.devices.push(Device {
+            uuid: vertex.uuid().to_string(),
+            id: vertex.id(),
+            description: vertex.description(),
+            image: vi.image().unwrap_or("").to_string(),
+            role: vi.role().as_str().to_string(),
+            command: vi.command().clone(),
+            env: vi.env().iter().map(|(k, v)| {(k.clone(), v.clone())}).collect(),
+            cpu: resources.cpu,
+            memory: resources.memory,
+            total_ports: vi.total_ports().unwrap_or(0),
+        });
+    }
 
-            let request = tonic::Request::new(HelloRequest {
-                name: "Tonic".into(),
-            });
-        
-            let _ = server_client.say_hello(request).await?;
-        
-            println!("Get the fucking resposne");
-        
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty() && self.links.is_empty()
+    }
 
+    pub fn add_link(&mut self, edge: &Edge) {
+        let edge_uuid = edge.edge_uuid();
+        let attrs = edge.attrs();
+        let ports = edge.ports();
+        let (vxlan, vni, data_ips) = match edge.realization() {
+            Some(LinkRealization::Vxlan { vni, data_ips }) => (true, *vni, (data_ips.0.to_string(), data_ips.1.to_string())),
+            _ => (false, 0, (String::new(), String::new())),
+        };
+        self.links.push(Link {
+            uuid0: edge_uuid.0.to_string(),
+            uuid1: edge_uuid.1.to_string(),
+            description: edge.description(),
+            rate_kbps: attrs.rate_kbps.unwrap_or(0),
+            delay_us: attrs.delay_us.unwrap_or(0),
+            jitter_us: attrs.jitter_us.unwrap_or(0),
+            loss_percent: attrs.loss_percent.unwrap_or(0.0),
+            mtu: attrs.mtu.unwrap_or(0),
+            queue_size: attrs.queue_size.unwrap_or(0),
+            has_ports: ports.is_some(),
+            port0: ports.map(|p| {p.0}).unwrap_or(0),
+            port1: ports.map(|p| {p.1}).unwrap_or(0),
+            vxlan,
+            vni,
+            data_ip0: data_ips.0,
+            data_ip1: data_ips.1,
+        });
+    }
+}
+
+// Fail all the devices in the assignment with the same error message.
+fn fail_all(assignment: &ServerAssignment, err_msg: String) -> HashMap<Uuid, DeviceState> {
+    assignment.devices.iter().fold(HashMap::new(), |mut map, dev| {
+        map.insert(dev.uuid.parse().unwrap(), DeviceState::Failed(err_msg.clone()));
+        map
+    })
+}
+
+// Apply the failed operations reported by the container server to the device states.
+//
+// A failed link fails both of its end devices that are launched on the server.
+fn apply_failures(states: &mut HashMap<Uuid, DeviceState>, results: Vec<OpResult>) {
+    for res in results.into_iter().filter(|res| {!res.success}) {
+        for uuid in res.uuids.iter().filter_map(|s| {s.parse::<Uuid>().ok()}) {
+            if let Some(DeviceState::Running) = states.get(&uuid) {
+                states.insert(uuid, DeviceState::Failed(res.message.clone()));
+            }
         }
-        
-        Ok(())
     }
 }
 
+/// Drive the container server to create the devices and links in the assignment.
+///
+/// `emunet` is the name of the emunet that the devices belong to.
+/// Return the launch state of every device in the assignment.
+pub async fn launch(emunet: String, assignment: ServerAssignment) -> HashMap<Uuid, DeviceState> {
+    // connect to the container server
+    let dst = format!("http://{}", &assignment.conn_addr);
+    let mut client = match timeout(CONN_TIMEOUT, ContainerAgentClient::connect(dst)).await {
+        Ok(Ok(client)) => client,
+        Ok(Err(err)) => return fail_all(&assignment, format!("fail to connect to {}: {}", &assignment.conn_addr, err)),
+        Err(_) => return fail_all(&assignment, format!("connection to {} timeout", &assignment.conn_addr)),
+    };
+
+    // create the devices
+    let mut states: HashMap<Uuid, DeviceState> = assignment.devices.iter().fold(HashMap::new(), |mut map, dev| {
+        map.insert(dev.uuid.parse().unwrap(), DeviceState::Running);
+        map
+    });
+    let request = tonic::Request::new(CreateDevicesRequest {
+        emunet: emunet.clone(),
+        devices: assignment.devices.clone(),
+    });
+    match client.create_devices(request).await {
+        Ok(resp) => apply_failures(&mut states, resp.into_inner().results),
+        Err(status) => return fail_all(&assignment, format!("fail to create devices: {}", status)),
+    };
+
+    // create the links attached to the devices
+    let request = tonic::Request::new(CreateLinksRequest {
+        emunet,
+        links: assignment.links.clone(),
+    });
+    match client.create_links(request).await {
+        Ok(resp) => apply_failures(&mut states, resp.into_inner().results),
+        Err(status) => {
+            let err_msg = format!("fail to create links: {}", status);
+            for state in states.values_mut() {
+                if let DeviceState::Running = state {
+                    *state = DeviceState::Failed(err_msg.clone());
+                }
+            }
+        }
+    };
+
+    states
+}
+
+// Collect the error messages of the failed operations.
+fn collect_failures(results: Vec<OpResult>) -> Vec<String> {
+    results.into_iter().filter(|res| {!res.success}).map(|res| {
+        format!("{}: {}", res.uuids.join("-"), res.message)
+    }).collect()
+}
+
+/// Drive the container server to delete the links and then the devices in the assignment.
+///
+/// The links attached to the deleted devices are removed together with the devices.
+/// Return the error messages of the failed deletions.
+pub async fn remove(emunet: String, assignment: ServerAssignment) -> Result<(), String> {
+    // connect to the container server
+    let dst = format!("http://{}", &assignment.conn_addr);
+    let mut client = match timeout(CONN_TIMEOUT, ContainerAgentClient::connect(dst)).await {
+        Ok(Ok(client)) => client,
+        Ok(Err(err)) => return Err(format!("fail to connect to {}: {}", &assignment.conn_addr, err)),
+        Err(_) => return Err(format!("connection to {} timeout", &assignment.conn_addr)),
+    };
+    let mut failures = Vec::new();
+
+    // delete the links
+    if !assignment.links.is_empty() {
+        let request = tonic::Request::new(DeleteLinksRequest {
+            emunet: emunet.clone(),
+            links: assignment.links.clone(),
+        });
+        match client.delete_links(request).await {
+            Ok(resp) => failures.extend(collect_failures(resp.into_inner().results)),
+            Err(status) => failures.push(format!("fail to delete links: {}", status)),
+        };
+    }
+
+    // delete the devices
+    if !assignment.devices.is_empty() {
+        let request = tonic::Request::new(DeleteDevicesRequest {
+            emunet,
+            uuids: assignment.devices.iter().map(|dev| {dev.uuid.clone()}).collect(),
+        });
+        match client.delete_devices(request).await {
+            Ok(resp) => failures.extend(collect_failures(resp.into_inner().results)),
+            Err(status) => failures.push(format!("fail to delete devices: {}", status)),
+        };
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    }
+    else {
+        Err(failures.join(", "))
+    }
+}
@@ -3,16 +3,16 @@ use std::future::Future;
 use std::collections::HashMap;
 use std::iter::Iterator;
 
-use futures::AsyncReadExt;
-use capnp_rpc::rpc_twoparty_capnp::Side;
-use capnp_rpc::{twoparty, RpcSystem};
 use uuid::Uuid;
-use indradb::{BulkInsertItem, Vertex, RangeVertexQuery, Type};
+use indradb::{BulkInsertItem, Vertex, RangeVertexQuery, SpecificVertexQuery, VertexQueryExt, Type};
+use indradb::{EdgeKey, SpecificEdgeQuery};
 
-use super::indradb::Backend as IndradbBackend;
+use super::indradb::CapnpBackend;
+use super::indradb::MemoryBackend;
 use super::indradb::build_backend_fut;
 use super::indradb::message_queue;
 use crate::emunet::{server, user, net};
+use crate::emunet::link_plan::VniPool;
 use super::ClientError;
 use super::indradb::Frontend as IndradbFrontend;
 use super::CORE_INFO_ID;
@@ -64,6 +64,9 @@ impl Client {
 
                 // initialize server list                
                 self.fe.set_server_info_list(servers).await?;
+
+                // initialize the vni pool
+                self.fe.set_vni_pool(VniPool::default()).await?;
                         
                 succeed!(())
             },
@@ -75,65 +78,297 @@ impl Client {
     /// 
     /// Return value has similar meaning as `Client::init`.
     pub async fn register_user(&self, user_name: &str) -> Result<QueryResult<()>, ClientError> {
-        // read current user map
-        let mut user_map: HashMap<String, user::EmuNetUser> = self.fe.get_user_map().await?;
-        if user_map.get(user_name).is_some() {
-            return fail!("user has already registered".to_string());
-        }
+        // atomically update the user map
+        self.fe.update_user_map(|user_map| {
+            if user_map.get(user_name).is_some() {
+                return Err("user has already registered".to_string());
+            }
 
-        // register the new user
-        let user = user::EmuNetUser::new(&user_name);
-        user_map.insert(user_name.to_string(), user);
-        
-        // sync update in the db
-        self.fe.set_user_map(user_map).await?;
-        
-        succeed!(())
+            // register the new user
+            let user = user::EmuNetUser::new(&user_name);
+            user_map.insert(user_name.to_string(), user);
+            Ok(())
+        }).await
     }
 
 
     /// Create a new emulation net for `user` with `name` and `capacity`.
     /// 
+    /// The capacity is allocated from the server pool with `policy`.
     /// Return value has similar meaning as `Client::init`.
-    pub async fn create_emu_net(&self, user: String, net: String, capacity: u32) -> Result<QueryResult<Uuid>, ClientError> {
-        // get the user
-        let mut user_map: HashMap<String, user::EmuNetUser> = self.fe.get_user_map().await?;
-        if user_map.get(&user).is_none() {
-            return fail!("invalid user name".to_string());
-        }
-        let user_mut = user_map.get_mut(&user).unwrap();
-
-        // check whether the emunet has existed
-        if user_mut.emu_net_exist(&net) {
-            return fail!("invalid emu-net name".to_string())
-        }
+    pub async fn create_emu_net(&self, user: String, net: String, capacity: u32, policy: server::AllocationPolicy) 
+        -> Result<QueryResult<Uuid>, ClientError> 
+    {
+        // the uuid of the emu net is generated in advance, so that the server allocation
+        // and the user map can be updated in a single atomic step
+        let emu_net_id = indradb::util::generate_uuid_v1();
+
+        let res = self.fe.update_core_info(|user_map, server_info_list| {
+            // get the user
+            let user_mut = match user_map.get_mut(&user) {
+                Some(user_mut) => user_mut,
+                None => return Err("invalid user name".to_string()),
+            };
+
+            // check whether the emunet has existed
+            if user_mut.emu_net_exist(&net) {
+                return Err("invalid emu-net name".to_string());
+            }
 
-        // get the allocation of servers
-        let server_info_list: Vec<server::ServerInfo> = self.fe.get_server_info_list().await?;
-        let mut sp = server::ServerInfoList::from_iterator(server_info_list.into_iter()).unwrap();
-        let allocation = match sp.allocate_servers(capacity) {
+            // get the allocation of servers
+            let mut sp = server::ServerInfoList::from_stored(server_info_list.iter().cloned());
+            let allocation = match sp.allocate_servers(capacity, policy) {
+                Ok(alloc) => alloc,
+                Err(remaining) => return Err(format!("not enough capacity at backend, remaining capacity: {}", remaining)),
+            };
+            *server_info_list = sp.into_vec();
+
+            // add the new emunet to user map
+            user_mut.add_emu_net(net.clone(), emu_net_id.clone());
+            Ok(allocation)
+        }).await?;
+        let allocation = match res {
             Ok(alloc) => alloc,
-            Err(remaining) => return fail!(format!("not enough capacity at backend, remaining capacity: {}", remaining)),
+            Err(err_msg) => return fail!(err_msg),
         };
-        self.fe.set_server_info_list(sp.into_vec()).await?;
         
-        // create a new emu net node
-        let emu_net_id = self.fe.create_vertex(None).await?.expect("vertex ID already exists");
-        // create a new emu net
-        let mut emu_net = net::EmuNet::new(user, net.clone(), emu_net_id.clone(), capacity);
-        emu_net.add_servers(allocation);
-        // initialize the EmuNet in the database
+        // create a new emu net and store it in a new vertex
+        let mut emu_net = net::EmuNet::new(user.clone(), net.clone(), emu_net_id.clone(), capacity);
+        emu_net.add_servers(allocation.clone());
+        let res = self.store_new_emu_net(emu_net).await;
+        if let Ok(Ok(())) = res {
+            return succeed!(emu_net_id);
+        }
+
+        // atomically undo the allocation and remove the emunet from the user map
+        let undo = self.fe.update_core_info(|user_map, server_info_list| {
+            if let Some(user_mut) = user_map.get_mut(&user) {
+                user_mut.remove_emu_net(&net);
+            }
+            let mut sp = server::ServerInfoList::from_stored(server_info_list.iter().cloned());
+            sp.release_servers(allocation).map(|_| {
+                *server_info_list = sp.into_vec();
+            })
+        }).await;
+        match undo {
+            Ok(Err(err)) => log::error!("fail to release the servers of emunet {}: {}", emu_net_id, err),
+            Err(err) => log::error!("fail to release the servers of emunet {}: {}", emu_net_id, err),
+            _ => {},
+        };
+        res.map(|res| {res.map(|_| {emu_net_id})})
+    }
+
+    // create the vertex of a new emunet and store the emunet in it
+    async fn store_new_emu_net(&self, emu_net: net::EmuNet) -> Result<QueryResult<()>, ClientError> {
+        let uuid = emu_net.uuid().clone();
+        if self.fe.create_vertex(Some(uuid.clone())).await?.is_none() {
+            return fail!(format!("vertex {} already exists", uuid));
+        }
         let jv = serde_json::to_value(emu_net).unwrap();
-        let res = self.fe.set_vertex_json_value(emu_net_id, "default", jv).await?;
-        if !res {
-            panic!("vertex not exist");
+        let res = self.fe.set_vertex_json_value(uuid.clone(), "default", jv).await;
+        if let Ok(true) = res {
+            return succeed!(());
+        }
+
+        // remove the vertex that does not hold the emunet
+        let _ = self.fe.delete_vertices(SpecificVertexQuery::single(uuid.clone()).into()).await;
+        match res {
+            Ok(_) => fail!(format!("vertex {} does not exist", uuid)),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Delete the emulation net `net` of `user`.
+    ///
+    /// The emunet vertex and all the device vertexes of the emunet are removed from the database,
+    /// and the servers allocated to the emunet are returned to the server pool with their capacity restored.
+    /// The containers of the emunet should be removed from the servers beforehand, by the caller 
+    /// that moves the emunet into working state.
+    ///
+    /// Return value has similar meaning as `Client::init`.
+    pub async fn delete_emu_net(&self, user: String, net: String) -> Result<QueryResult<()>, ClientError> {
+        // get the uuid of the emunet
+        let user_map: HashMap<String, user::EmuNetUser> = self.fe.get_user_map().await?;
+        let emu_net_id = match user_map.get(&user) {
+            Some(user) => match user.get_emu_net(&net) {
+                Some(id) => id,
+                None => return fail!("invalid emu-net name".to_string()),
+            },
+            None => return fail!("invalid user name".to_string()),
+        };
+
+        // retrieve the emunet object
+        let mut emu_net: net::EmuNet = match self.fe.get_vertex_json_value(emu_net_id, "default").await? {
+            Some(jv) => serde_json::from_value(jv).unwrap(),
+            None => return fail!("emunet not exist".to_string()),
+        };
+
+        // atomically remove the emunet from the user map and return the allocated 
+        // servers to the server pool, the vertexes are kept if this fails so that
+        // the deletion can be retried
+        let servers = emu_net.release_servers();
+        let res = self.fe.update_core_info(|user_map, server_info_list| {
+            // a concurrent deletion may have already released the servers
+            let user_mut = match user_map.get_mut(&user) {
+                Some(user_mut) => user_mut,
+                None => return Err("invalid user name".to_string()),
+            };
+            if user_mut.remove_emu_net(&net).is_none() {
+                return Err("invalid emu-net name".to_string());
+            }
+
+            let mut sp = server::ServerInfoList::from_stored(server_info_list.iter().cloned());
+            sp.release_servers(servers)?;
+            *server_info_list = sp.into_vec();
+            Ok(())
+        }).await?;
+        if res.is_err() {
+            return Ok(res);
         }
 
-        // add the new emunet to user map
-        user_mut.add_emu_net(net, emu_net_id.clone());
-        self.fe.set_user_map(user_map).await?;
+        // delete all the device vertexes, then the emunet vertex
+        let q = RangeVertexQuery::new(u32::MAX).t(Type::new(emu_net.vertex_type()).unwrap());
+        self.fe.delete_vertices(q.into()).await?;
+        self.fe.delete_vertices(SpecificVertexQuery::single(emu_net_id).into()).await?;
 
-        succeed!(emu_net_id)
+        // return the VNIs of the tunnels of the emunet
+        self.fe.update_vni_pool(|vni_pool| {
+            vni_pool.release_emunet(&emu_net_id);
+            Ok(())
+        }).await
+    }
+
+    /// Allocate `count` VNIs to the emunet with `emunet_uuid`.
+    /// 
+    /// Return value has similar meaning as `Client::init`.
+    pub async fn allocate_vnis(&self, emunet_uuid: Uuid, count: usize) -> Result<QueryResult<Vec<u32>>, ClientError> {
+        if count == 0 {
+            return succeed!(Vec::new());
+        }
+        let res = self.fe.update_vni_pool(|vni_pool| {
+            vni_pool.alloc(emunet_uuid, count)
+        }).await?;
+        Ok(res)
+    }
+
+    /// Return the `vnis` to the vni pool.
+    pub async fn release_vnis(&self, vnis: Vec<u32>) -> Result<QueryResult<()>, ClientError> {
+        if vnis.is_empty() {
+            return succeed!(());
+        }
+        let res = self.fe.update_vni_pool(|vni_pool| {
+            vni_pool.release(&vnis);
+            Ok(())
+        }).await?;
+        Ok(res)
+    }
+
+    /// Add a new container server to the server pool.
+    /// 
+    /// Return the id of the new server. The return value has similar meaning as `Client::init`.
+    pub async fn add_server(&self, conn_ip: &str, conn_port: u16, data_ip: &str, man_ip: &str, 
+                            max_capacity: u32, max_cpu: u32, max_memory: u32) -> Result<QueryResult<Uuid>, ClientError> 
+    {
+        self.fe.update_server_info_list(|server_info_list| {
+            let mut sp = server::ServerInfoList::from_stored(server_info_list.iter().cloned());
+            let id = sp.add_server_info(conn_ip, conn_port, data_ip, man_ip, max_capacity, max_cpu, max_memory).map_err(|e| {
+                format!("{}", e)
+            })?;
+            *server_info_list = sp.into_vec();
+            Ok(id)
+        }).await
+    }
+
+    /// Reconcile the server pool with the configured `servers`, see `ServerInfoList::reconcile`.
+    /// 
+    /// Return value has similar meaning as `Client::init`.
+    pub async fn reconcile_servers(&self, servers: Vec<server::ServerInfo>) 
+        -> Result<QueryResult<server::ReconcileReport>, ClientError> 
+    {
+        self.fe.update_server_info_list(|server_info_list| {
+            let mut sp = server::ServerInfoList::from_stored(server_info_list.iter().cloned());
+            let report = sp.reconcile(servers).map_err(|e| {format!("{}", e)})?;
+            *server_info_list = sp.into_vec();
+            Ok(report)
+        }).await
+    }
+
+    /// List all the container servers in the server pool.
+    pub async fn list_servers(&self) -> Result<QueryResult<Vec<server::ServerInfo>>, ClientError> {
+        let server_info_list = self.fe.get_server_info_list().await?;
+        succeed!(server_info_list)
+    }
+
+    /// Exclude a container server from new allocations if `draining` is set, 
+    /// or include it again otherwise.
+    /// 
+    /// Return value has similar meaning as `Client::init`.
+    pub async fn drain_server(&self, id: Uuid, draining: bool) -> Result<QueryResult<()>, ClientError> {
+        self.fe.update_server_info_list(|server_info_list| {
+            let mut sp = server::ServerInfoList::from_stored(server_info_list.iter().cloned());
+            sp.drain_server(id, draining)?;
+            *server_info_list = sp.into_vec();
+            Ok(())
+        }).await
+    }
+
+    /// Record the results of the health checks on the container servers, `health` maps
+    /// the id of each checked server to whether it is up.
+    /// 
+    /// `now` is the time of the checks in seconds since the UNIX epoch.
+    /// Return the ids of all the servers that are down.
+    pub async fn update_server_health(&self, health: HashMap<Uuid, bool>, now: u64) -> Result<QueryResult<Vec<Uuid>>, ClientError> {
+        self.fe.update_server_info_list(|server_info_list| {
+            let mut sp = server::ServerInfoList::from_stored(server_info_list.iter().cloned());
+            for (id, up) in health.into_iter() {
+                // the server may have been removed during the check
+                let _ = sp.set_health(id, up, now);
+            }
+            *server_info_list = sp.into_vec();
+            
+            let down = server_info_list.iter().filter(|si| {si.health() == server::ServerHealth::Down}).map(|si| {si.id()});
+            Ok::<_, String>(down.collect())
+        }).await
+    }
+
+    /// Remove a container server that is not used by any emunet from the server pool.
+    /// 
+    /// Return value has similar meaning as `Client::init`.
+    pub async fn remove_server(&self, id: Uuid) -> Result<QueryResult<()>, ClientError> {
+        // the allocation of the server before checking the emunets
+        let allocated = match self.fe.get_server_info_list().await?.into_iter().find(|si| {si.id() == id}) {
+            Some(si) => si.allocated(),
+            None => return fail!(format!("server {} does not exist", id)),
+        };
+
+        // find the emunets that use the server
+        let uuids = match self.list_all_emu_net_uuids().await? {
+            Ok(uuids) => uuids,
+            Err(err) => return fail!(err),
+        };
+        let mut users = Vec::new();
+        for uuid in uuids.into_iter() {
+            match self.get_emu_net(uuid).await? {
+                Ok(emu_net) => {
+                    if emu_net.servers().any(|cs| {cs.id() == id}) {
+                        users.push(uuid.to_string());
+                    }
+                },
+                // the emunet is being created or deleted
+                Err(_) => return fail!("emu-nets are being created or deleted, try again later".to_string()),
+            }
+        }
+        if !users.is_empty() {
+            return fail!(format!("server {} is used by emu-nets {}", id, users.join(", ")));
+        }
+
+        // an emunet allocated on the server after the check changes its allocation
+        self.fe.update_server_info_list(|server_info_list| {
+            let mut sp = server::ServerInfoList::from_stored(server_info_list.iter().cloned());
+            sp.remove_server(id, allocated)?;
+            *server_info_list = sp.into_vec();
+            Ok(())
+        }).await
     }
 
     /// List all the emunet of a user.
@@ -150,6 +385,13 @@ impl Client {
         succeed!(user.get_all_emu_nets())
     }
 
+    /// List the uuids of the emunets of all the users.
+    pub async fn list_all_emu_net_uuids(&self) -> Result<QueryResult<Vec<Uuid>>, ClientError> {
+        let user_map: HashMap<String, user::EmuNetUser> = self.fe.get_user_map().await?;
+        let uuids = user_map.values().flat_map(|user| {user.get_all_emu_nets().into_iter().map(|(_, uuid)| {uuid})});
+        succeed!(uuids.collect())
+    }
+
     /// Get the emunet from an uuid.
     /// 
     /// Note: I don't know if this is necessary as well.
@@ -161,11 +403,11 @@ impl Client {
         }
     }
 
-    /// Get the client-side emunet information from the database.
+    /// Get the device vertexes and the edges of the emunet from the database.
     /// 
-    /// Note: I don't know if this is necessary as well.
-    pub async fn get_emu_net_infos(&self, emunet: &net::EmuNet) 
-    -> Result<QueryResult<(Vec<net::VertexInfo>, Vec<net::EdgeInfo>)>, ClientError> 
+    /// Each undirected edge is stored as a single directed edge in the database.
+    pub async fn get_emu_net_graph(&self, emunet: &net::EmuNet) 
+    -> Result<QueryResult<(Vec<net::Vertex>, Vec<net::Edge>)>, ClientError> 
     {
         // acquire the minimum uuid of the vertex
         let minium_uuid_opt = emunet.vertex_uuids().fold(None, |opt, uuid| {
@@ -187,61 +429,108 @@ impl Client {
         }
         let minimum_uuid = minium_uuid_opt.unwrap().clone();
 
-        // build up the query and acquire the vertex map from the backend
+        // build up the query and acquire the vertexes from the backend
         let q = RangeVertexQuery::new(u32::MAX).start_id(minimum_uuid).t(Type::new(emunet.vertex_type()).unwrap());
-        let vertex_map: HashMap<uuid::Uuid, net::Vertex> = 
-            self.fe.get_vertex_properties(q).await?.into_iter().fold(HashMap::new(), |mut map, jv| {
-                let v: net::Vertex = serde_json::from_value(jv).unwrap();
-                let res = map.insert(v.uuid(), v);
-                if !res.is_none() {
-                    panic!("this should never happen!")
-                }
-                map
-            });
+        let vertexes: Vec<net::Vertex> = self.fe.get_vertex_properties(q.clone()).await?.into_iter().map(|jv| {
+            serde_json::from_value(jv).unwrap()
+        }).collect();
         
-        // build up the list of edge_info
-        let edge_infos: HashMap<(u64, u64), net::EdgeInfo> = vertex_map.values().fold(HashMap::new(), |map, v| {
-            let edges = v.edges();
-            edges.fold(map, |mut map, edge| {
-                // build up the client-side edge id
-                let edge_uuid = edge.edge_uuid();
-                let edge_id = (vertex_map.get(& edge_uuid.0).unwrap().id(), vertex_map.get(& edge_uuid.1).unwrap().id());
-                // build up the rest of the fields needed to construct EdgeInfo
-                let description = edge.description();
-
-                // the EdgeInfo contains undirected edge, so only one of the directed
-                // edges between a pair of vertexes is inserted into the hash map
-                if !map.contains_key(&(edge_id.1, edge_id.0)) {
-                    let ei = net::EdgeInfo::new(edge_id, description);
-                    if map.insert(edge_id, ei).is_some() {
-                        panic!("this should not happen!");
-                    }
-                };
-                map
-            })
-        });
-        // build up the list of vertex_info
-        let vertex_infos = vertex_map.values().fold(Vec::new(), |mut vec, v| {
-            vec.push(v.vertex_info());
-            vec
+        // acquire all the edges going out of the vertexes, the parallel edges have different types
+        let q = q.outbound(u32::MAX);
+        let edges: Vec<net::Edge> = self.fe.get_edge_properties(q).await?.into_iter().map(|jv| {
+            serde_json::from_value(jv).unwrap()
+        }).collect();
+
+        succeed!((vertexes, edges))
+    }
+
+    /// Get the client-side emunet information from the database.
+    /// 
+    /// Note: I don't know if this is necessary as well.
+    pub async fn get_emu_net_infos(&self, emunet: &net::EmuNet) 
+    -> Result<QueryResult<(Vec<net::VertexInfo>, Vec<net::EdgeInfo>)>, ClientError> 
+    {
+        let (vertexes, edges) = match self.get_emu_net_graph(emunet).await? {
+            Ok(graph) => graph,
+            Err(err) => return fail!(err),
+        };
+        let vertex_map: HashMap<uuid::Uuid, net::Vertex> = vertexes.into_iter().fold(HashMap::new(), |mut map, v| {
+            let res = map.insert(v.uuid(), v);
+            if !res.is_none() {
+                panic!("this should never happen!")
+            }
+            map
         });
 
-        succeed!((vertex_infos, edge_infos.into_iter().map(|(_, v)|{v}).collect()))
+        // build up the list of edge_info from the client-side edge ids
+        let edge_infos: Vec<net::EdgeInfo> = edges.into_iter().map(|edge| {
+            let edge_uuid = edge.edge_uuid();
+            let edge_id = (vertex_map.get(& edge_uuid.0).unwrap().id(), vertex_map.get(& edge_uuid.1).unwrap().id());
+            net::EdgeInfo::new(edge_id, edge.description(), edge.attrs().clone(), edge.ports(), edge.addrs().cloned())
+        }).collect();
+
+        // build up the list of vertex_info, with the interfaces taken by the edges
+        let mut vertex_infos: HashMap<u64, net::VertexInfo> = vertex_map.values().map(|v| {(v.id(), v.vertex_info())}).collect();
+        for ei in edge_infos.iter() {
+            if let Some((port0, port1)) = ei.ports() {
+                let (id0, id1) = ei.edge_id();
+                vertex_infos.get_mut(&id0).unwrap().add_interface(net::Interface {
+                    index: port0,
+                    name: net::interface_name(port0),
+                    peer: (id1, port1),
+                });
+                vertex_infos.get_mut(&id1).unwrap().add_interface(net::Interface {
+                    index: port1,
+                    name: net::interface_name(port1),
+                    peer: (id0, port0),
+                });
+            }
+        }
+        let vertex_infos: Vec<net::VertexInfo> = vertex_infos.into_iter().map(|(_, vi)| {vi}).collect();
+
+        succeed!((vertex_infos, edge_infos))
+    }
+
+    /// Get the uuids of the neighbouring vertexes of vertex `vertex_uuid`.
+    /// 
+    /// The neighbours are acquired by querying the edges of the vertex in both directions.
+    pub async fn get_neighbours(&self, vertex_uuid: Uuid) -> Result<QueryResult<Vec<Uuid>>, ClientError> {
+        // the vertexes that are pointed to by the vertex
+        let q = SpecificVertexQuery::single(vertex_uuid.clone()).outbound(u32::MAX);
+        let mut neighbours: Vec<Uuid> = self.fe.get_edges(q.into()).await?.into_iter().map(|e| {e.key.inbound_id}).collect();
+        
+        // the vertexes that point to the vertex
+        let q = SpecificVertexQuery::single(vertex_uuid).inbound(u32::MAX);
+        neighbours.extend(self.fe.get_edges(q.into()).await?.into_iter().map(|e| {e.key.outbound_id}));
+
+        succeed!(neighbours)
     }
 
     /// Get the emunet from an uuid.
     /// 
     /// Note: I don't know if this is necessary as well.
     pub async fn set_emu_net(&self, emu_net: net::EmuNet) -> Result<QueryResult<()>, ClientError> {
-        let uuid = emu_net.uuid().clone();
-        let jv = serde_json::to_value(emu_net).unwrap();
-        let res = self.fe.set_vertex_json_value(uuid, "default", jv).await?;
+        let res = self.fe.set_emu_net(emu_net).await?;
         match res {
             false => fail!("EmuNet not exist".to_string()),
             true => succeed!(()),
         }
     }
 
+    /// Atomically update the emunet with `uuid` by `f`, the emunet is stored only if `f` returns Ok.
+    /// 
+    /// Return value has similar meaning as `Client::init`.
+    pub async fn update_emu_net<F, R>(&self, uuid: Uuid, f: F) -> Result<QueryResult<R>, ClientError> 
+    where
+        F: FnOnce(&mut net::EmuNet) -> Result<R, String>
+    {
+        let res = self.fe.update_emu_net(uuid, f).await?;
+        match res {
+            Some(res) => Ok(res),
+            None => fail!("emunet not exist".to_string()),
+        }
+    }
+
     /// Create a bulk of vertexes from a vector of vertex uuids.
     /// 
     /// Note, we assume this method to be never fail. 
@@ -261,6 +550,66 @@ impl Client {
         succeed!(())
     }
 
+    /// Create a bulk of directed edges from a vector of (outbound vertex uuid, edge type, inbound vertex uuid).
+    /// 
+    /// Note, we assume this method to be never fail. 
+    /// However, if any of the two vertexes of an edge is not created in the database, this method can 
+    /// still finish without returning useful error messages. 
+    /// Consider repairing this in the future?
+    pub async fn bulk_create_edges<I: Iterator<Item = (Uuid, String, Uuid)>>(&self, edges: I)
+    -> Result<QueryResult<()>, ClientError> 
+    {
+        let qs: Vec<BulkInsertItem> = edges.fold(Vec::new(), |mut qs, edge_key| {
+            let key = EdgeKey::new(edge_key.0, Type::new(&edge_key.1).unwrap(), edge_key.2);
+            qs.push(BulkInsertItem::Edge(key));
+            qs
+        });
+
+        self.fe.bulk_insert(qs).await?;
+        succeed!(())
+    }
+
+    /// Delete a bulk of vertexes, together with their properties and edges.
+    pub async fn bulk_delete_vertexes<I: Iterator<Item = Uuid>>(&self, vertexes: I)
+    -> Result<QueryResult<()>, ClientError> 
+    {
+        let q = SpecificVertexQuery::new(vertexes.collect());
+        self.fe.delete_vertices(q.into()).await?;
+        succeed!(())
+    }
+
+    /// Delete a bulk of directed edges from a vector of (outbound vertex uuid, edge type, inbound vertex uuid).
+    pub async fn bulk_delete_edges<I: Iterator<Item = (Uuid, String, Uuid)>>(&self, edges: I)
+    -> Result<QueryResult<()>, ClientError> 
+    {
+        let keys: Vec<EdgeKey> = edges.map(|edge_key| {
+            EdgeKey::new(edge_key.0, Type::new(&edge_key.1).unwrap(), edge_key.2)
+        }).collect();
+
+        self.fe.delete_edges(SpecificEdgeQuery::new(keys).into()).await?;
+        succeed!(())
+    }
+
+    /// Set properties for all the edges from the list, each edge is identified by 
+    /// (outbound vertex uuid, edge type, inbound vertex uuid).
+    /// 
+    /// Note, we assume this method to be never fail. 
+    /// However, if a particular edge is not created in the datbase, this method can still finish without 
+    /// returning useful error messages. 
+    /// Consider repairing this in the future?
+    pub async fn bulk_set_edge_properties<I: Iterator<Item = ((Uuid, String, Uuid), serde_json::Value)>>(&self, edge_properties: I) 
+    -> Result<QueryResult<()>, ClientError> 
+    {
+        let qs: Vec<BulkInsertItem> = edge_properties.fold(Vec::new(), |mut qs, edge_property| {
+            let key = EdgeKey::new((edge_property.0).0, Type::new(&(edge_property.0).1).unwrap(), (edge_property.0).2);
+            qs.push(BulkInsertItem::EdgeProperty(key, "default".to_string(), edge_property.1));
+            qs
+        });
+
+        self.fe.bulk_insert(qs).await?;
+        succeed!(())
+    }
+
     /// Set properties for all the vertexes from the list.
     /// 
     /// Note, we assume this method to be never fail. 
@@ -280,16 +629,50 @@ impl Client {
     }
 }
 
+// The storage that the launched client talks to.
+enum LaunchTarget {
+    // an external IndraDB server connected through capnp rpc
+    Capnp(tokio::net::TcpStream, std::net::SocketAddr),
+    // an IndraDB memory datastore running in-process
+    Memory,
+}
+
+// The default maximum number of requests that the backend serves concurrently.
+const DEFAULT_MAX_IN_FLIGHT: usize = 16;
+
 /// The launcher that runs the client in a closure. 
 pub struct ClientLauncher {
-    conn: tokio::net::TcpStream,
+    target: LaunchTarget,
+    max_in_flight: usize,
 }
 
 impl ClientLauncher {
     /// Make an async connection to the database and return a ClientLauncher.
     pub async fn connect(addr: &std::net::SocketAddr) -> Result<Self, std::io::Error> {
         let conn = tokio::net::TcpStream::connect(&addr).await?;
-        Ok(Self {conn})
+        Ok(Self {
+            target: LaunchTarget::Capnp(conn, addr.clone()),
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+        })
+    }
+
+    /// Return a ClientLauncher that stores everything in an in-process memory datastore.
+    /// 
+    /// No external database server is needed, which is useful for testing and single-node use.
+    pub fn in_memory() -> Self {
+        Self {
+            target: LaunchTarget::Memory,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+        }
+    }
+
+    /// Set the maximum number of database requests that can be served concurrently.
+    /// 
+    /// Setting it to 1 makes the backend serve the requests one at a time, 
+    /// and 0 is raised to 1.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = std::cmp::max(max_in_flight, 1);
+        self
     }
 
     /// Launch a background task and run the entry function.
@@ -302,33 +685,28 @@ impl ClientLauncher {
     {
         let ls = tokio::task::LocalSet::new();
         let (sender, queue) = message_queue::create();
+        let max_in_flight = self.max_in_flight;
         
         // every capnp-related struct is non Send, so must be launched in LocalSet
-        let backend_fut = ls.run_until(async move {         
-            // create rpc_system
-            let (reader, writer) = tokio_util::compat::Tokio02AsyncReadCompatExt::compat(self.conn).split();
-            let rpc_network = Box::new(twoparty::VatNetwork::new(
-                reader,
-                writer,
-                Side::Client,
-                Default::default(),
-            ));
-            let mut capnp_rpc_system = RpcSystem::new(rpc_network, None);
-            
-            // create client_backend
-            let indradb_capnp_client = capnp_rpc_system.bootstrap(Side::Server);
-            let disconnector = capnp_rpc_system.get_disconnector();
-            let indradb_client_backend = IndradbBackend::new(indradb_capnp_client, disconnector);
-    
-            // run rpc_system
-            tokio::task::spawn_local(async move {
-                capnp_rpc_system.await
-            });
-
-            // run indradb backend
-            tokio::task::spawn_local(build_backend_fut(indradb_client_backend, queue))
-                .await
-                .unwrap()
+        let backend_fut = ls.run_until(async move {
+            match self.target {
+                LaunchTarget::Capnp(conn, addr) => {
+                    // create client_backend, which launches the rpc system and 
+                    // reconnects to `addr` when the connection is lost
+                    let indradb_client_backend = CapnpBackend::new(conn, addr);
+
+                    // run indradb backend
+                    tokio::task::spawn_local(build_backend_fut(indradb_client_backend, queue, max_in_flight))
+                        .await
+                        .unwrap()
+                },
+                LaunchTarget::Memory => {
+                    // run the in-process backend
+                    tokio::task::spawn_local(build_backend_fut(MemoryBackend::new(), queue, max_in_flight))
+                        .await
+                        .unwrap()
+                }
+            }
         });
 
         // launch the backend task to run entry function
@@ -340,4 +718,186 @@ impl ClientLauncher {
         backend_fut.await?;
         entry_fn_jh.await.unwrap()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+impl Client {
+    // replace the stored server list without any validation
+    pub(crate) async fn replace_server_info_list(&self, servers: Vec<server::ServerInfo>) {
+        self.fe.set_server_info_list(servers).await.unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // three servers with 10 containers each
+    fn server_pool() -> Vec<server::ServerInfo> {
+        let mut sp = server::ServerInfoList::new();
+        for i in 1..4 {
+            sp.add_server_info(
+                &format!("127.0.0.{}", i), 10000, &format!("10.0.0.{}", i), &format!("10.1.0.{}", i), 10, 8000, 16384
+            ).unwrap();
+        }
+        sp.into_vec()
+    }
+
+    #[tokio::test]
+    async fn concurrent_create_emu_net_does_not_overcommit() {
+        ClientLauncher::in_memory().with_db_client(|client| async move {
+            client.init(server_pool()).await.unwrap().unwrap();
+            client.register_user("user").await.unwrap().unwrap();
+
+            // 10 emunets of 4 containers compete for 30 containers
+            let futs = (0..10).map(|i| {
+                let client = client.clone();
+                async move {
+                    client.create_emu_net("user".to_string(), format!("net{}", i), 4, server::AllocationPolicy::BestFit).await.unwrap()
+                }
+            });
+            let created = futures::future::join_all(futs).await.into_iter().filter(|res| {res.is_ok()}).count();
+            assert_eq!(created, 7);
+
+            let servers = client.list_servers().await.unwrap().unwrap();
+            let remaining: u32 = servers.iter().map(|si| {si.remaining_capacity()}).sum();
+            assert_eq!(remaining, 30 - 4 * created as u32);
+            Ok(())
+        }).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn remove_server_used_by_emu_net() {
+        ClientLauncher::in_memory().with_db_client(|client| async move {
+            client.init(server_pool()).await.unwrap().unwrap();
+            client.register_user("user").await.unwrap().unwrap();
+            let servers = client.list_servers().await.unwrap().unwrap();
+            client.create_emu_net("user".to_string(), "net".to_string(), 4, server::AllocationPolicy::FirstFit).await.unwrap().unwrap();
+
+            // the first server is used by the emunet even after it is drained
+            client.drain_server(servers[0].id(), true).await.unwrap().unwrap();
+            assert!(client.remove_server(servers[0].id()).await.unwrap().is_err());
+            client.remove_server(servers[1].id()).await.unwrap().unwrap();
+
+            client.delete_emu_net("user".to_string(), "net".to_string()).await.unwrap().unwrap();
+            client.remove_server(servers[0].id()).await.unwrap().unwrap();
+            assert_eq!(client.list_servers().await.unwrap().unwrap().len(), 1);
+            Ok(())
+        }).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_update_emu_net_is_atomic() {
+        ClientLauncher::in_memory().with_db_client(|client| async move {
+            client.init(server_pool()).await.unwrap().unwrap();
+            client.register_user("user").await.unwrap().unwrap();
+            let uuid = client.create_emu_net("user".to_string(), "net".to_string(), 4, server::AllocationPolicy::BestFit).await.unwrap().unwrap();
+
+            // only one of the concurrent transitions from uninit to working succeeds
+            let futs = (0..5).map(|_| {
+                client.update_emu_net(uuid, |emu_net| {
+                    if !emu_net.is_uninit() {
+                        return Err("emu-net is initialized".to_string());
+                    }
+                    emu_net.working();
+                    Ok(())
+                })
+            });
+            let updated = futures::future::join_all(futs).await.into_iter().filter(|res| {
+                res.as_ref().unwrap().is_ok()
+            }).count();
+            assert_eq!(updated, 1);
+            assert!(client.get_emu_net(uuid).await.unwrap().unwrap().is_working());
+            Ok(())
+        }).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_delete_emu_net_can_be_retried() {
+        ClientLauncher::in_memory().with_db_client(|client| async move {
+            client.init(server_pool()).await.unwrap().unwrap();
+            client.register_user("user").await.unwrap().unwrap();
+            let uuid = client.create_emu_net("user".to_string(), "net".to_string(), 4, server::AllocationPolicy::FirstFit).await.unwrap().unwrap();
+
+            // the server of the emunet is replaced by another one with the same connection 
+            // address, so the server can not be returned to the pool
+            let stored = client.fe.get_server_info_list().await.unwrap();
+            let mut replaced = server::ServerInfoList::new();
+            replaced.add_server_info("127.0.0.1", 10000, "10.0.0.9", "10.1.0.9", 10, 8000, 16384).unwrap();
+            client.fe.set_server_info_list(replaced.into_vec()).await.unwrap();
+            assert!(client.delete_emu_net("user".to_string(), "net".to_string()).await.unwrap().is_err());
+            assert!(client.get_emu_net(uuid).await.unwrap().is_ok());
+
+            // the deletion succeeds once the server is back
+            client.fe.set_server_info_list(stored).await.unwrap();
+            client.delete_emu_net("user".to_string(), "net".to_string()).await.unwrap().unwrap();
+            assert!(client.get_emu_net(uuid).await.unwrap().is_err());
+            let remaining: u32 = client.list_servers().await.unwrap().unwrap().iter().map(|si| {si.remaining_capacity()}).sum();
+            assert_eq!(remaining, 30);
+            Ok(())
+        }).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_delete_emu_net_releases_once() {
+        ClientLauncher::in_memory().with_db_client(|client| async move {
+            client.init(server_pool()).await.unwrap().unwrap();
+            client.register_user("user").await.unwrap().unwrap();
+            client.create_emu_net("user".to_string(), "net".to_string(), 12, server::AllocationPolicy::BestFit).await.unwrap().unwrap();
+
+            let del = || {client.delete_emu_net("user".to_string(), "net".to_string())};
+            let (res0, res1) = futures::future::join(del(), del()).await;
+            assert_eq!(res0.unwrap().is_ok() as u32 + res1.unwrap().is_ok() as u32, 1);
+
+            let servers = client.list_servers().await.unwrap().unwrap();
+            let remaining: u32 = servers.iter().map(|si| {si.remaining_capacity()}).sum();
+            assert_eq!(remaining, 30);
+            Ok(())
+        }).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn parallel_links_round_trip() {
+        ClientLauncher::in_memory().with_db_client(|client| async move {
+            client.init(server_pool()).await.unwrap().unwrap();
+            client.register_user("user").await.unwrap().unwrap();
+            let uuid = client.create_emu_net("user".to_string(), "net".to_string(), 4, server::AllocationPolicy::FirstFit).await.unwrap().unwrap();
+            let mut emu_net = client.get_emu_net(uuid).await.unwrap().unwrap();
+            let server_uuid = emu_net.servers().next().unwrap().id();
+
+            let vertexes: Vec<net::Vertex> = (0..2).map(|id| {
+                let info = serde_json::from_value(serde_json::json!({"id": id, "description": format!("dev{}", id)})).unwrap();
+                net::Vertex::new(info, indradb::util::generate_uuid_v1(), server_uuid)
+            }).collect();
+            let (a, b) = (vertexes[0].uuid(), vertexes[1].uuid());
+            let mut edges: Vec<net::Edge> = vec!((a, b, (0, 0)), (b, a, (1, 1)), (a, b, (2, 2))).into_iter().map(|(v0, v1, ports)| {
+                net::Edge::new((v0, v1), format!("ports{}", ports.0), net::LinkAttrs::default(), Some(ports))
+            }).collect();
+            net::assign_link_indexes(std::iter::empty(), &mut edges);
+
+            client.bulk_create_vertexes(vertexes.iter().map(|v| {v.uuid()}), emu_net.vertex_type()).await.unwrap().unwrap();
+            client.bulk_set_vertex_properties(vertexes.iter().map(|v| {(v.uuid(), serde_json::to_value(v).unwrap())})).await.unwrap().unwrap();
+            client.bulk_create_edges(edges.iter().map(|e| {e.edge_key(&emu_net.vertex_type())})).await.unwrap().unwrap();
+            client.bulk_set_edge_properties(edges.iter().map(|e| {
+                (e.edge_key(&emu_net.vertex_type()), serde_json::to_value(e).unwrap())
+            })).await.unwrap().unwrap();
+            for v in vertexes.iter() {
+                emu_net.add_vertex(v.id(), v.uuid());
+            }
+
+            // each parallel link is stored as a distinct edge and read back with its ports
+            let (stored_vertexes, stored_edges) = client.get_emu_net_graph(&emu_net).await.unwrap().unwrap();
+            assert_eq!(stored_vertexes.len(), 2);
+            let mut stored: Vec<((Uuid, String, Uuid), Option<(u32, u32)>)> = stored_edges.iter().map(|e| {
+                (e.edge_key(&emu_net.vertex_type()), e.ports())
+            }).collect();
+            stored.sort_by_key(|(_, ports)| {*ports});
+            assert_eq!(stored, vec!(
+                ((a, "user-net".to_string(), b), Some((0, 0))),
+                ((b, "user-net-1".to_string(), a), Some((1, 1))),
+                ((a, "user-net-2".to_string(), b), Some((2, 2))),
+            ));
+            Ok(())
+        }).await.unwrap();
+    }
+}
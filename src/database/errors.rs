@@ -8,6 +8,8 @@ use tokio::sync::oneshot::error as oneshot;
 #[derive(Debug, Clone, Copy)]
 pub enum BackendErrorKind {
     CapnpError, // capnp rpc error, fatal
+    Disconnected, // the connection to the database is lost and is being re-established, retryable
+    DatastoreError, // in-process datastore error, fatal
     QueueDrop,  // the message queue has been dropped, fatal
 }
 
@@ -24,6 +26,22 @@ impl BackendError {
         self.kind
     }
 
+    /// Whether the failed request can be retried later.
+    pub fn is_retryable(&self) -> bool {
+        match self.kind {
+            BackendErrorKind::Disconnected => true,
+            _ => false,
+        }
+    }
+
+    /// Build an error indicating that the connection to the database is lost.
+    pub fn disconnected(description: String) -> Self {
+        Self {
+            kind: BackendErrorKind::Disconnected,
+            description,
+        }
+    }
+
     // Change the error message if the error kind is DataError.
     // pub fn change_data_error_msg(self, description: String) -> Self {
     //     match self.kind {
@@ -39,13 +57,27 @@ impl BackendError {
 // Convert capnp::Error into BackendError.
 impl From<capnp::Error> for BackendError {
     fn from(err: capnp::Error) -> Self {
+        let kind = match err.kind {
+            capnp::ErrorKind::Disconnected => BackendErrorKind::Disconnected,
+            _ => BackendErrorKind::CapnpError,
+        };
         Self {
-            kind: BackendErrorKind::CapnpError,
+            kind,
             description: format!("{}", err),
         }
     }
 }
 
+// Convert indradb::Error into BackendError.
+impl From<indradb::Error> for BackendError {
+    fn from(err: indradb::Error) -> Self {
+        Self {
+            kind: BackendErrorKind::DatastoreError,
+            description: format!("{:?}", err),
+        }
+    }
+}
+
 // Convert mpsc::SendError<T> into BackendError.
 impl<T> From<mpsc::SendError<T>> for BackendError {
     fn from(_: mpsc::SendError<T>) -> BackendError {
@@ -1,29 +1,222 @@
 // An implementation of Indradb storage backend
 use std::future::Future;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::net::SocketAddr;
+
+use futures::AsyncReadExt;
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::net::TcpStream;
+use tokio::time::{self, Duration};
+use capnp_rpc::{twoparty, RpcSystem};
 
 use capnp_rpc::rpc_twoparty_capnp::Side;
 use indradb::{Vertex, VertexQuery};
 use indradb::{VertexProperty, VertexPropertyQuery};
+use indradb::{Edge, EdgeKey, EdgeQuery};
+use indradb::{EdgeProperty, EdgePropertyQuery};
 use indradb::BulkInsertItem;
 use capnp::Error as CapnpError;
+use futures::future::LocalBoxFuture;
 
 use super::indradb_util::ClientTransaction;
 use super::indradb_util::converters;
-use super::message_queue::Queue;
+use super::message_queue::{Queue, Message};
 use super::message::{Request, Response};
-use crate::database::errors::BackendError;
+use crate::database::errors::{BackendError, BackendErrorKind};
+
+/// The storage backend that serves the requests sent by the `Frontend`.
+/// 
+/// The backend is run inside a `LocalSet`, so neither the backend nor the returned 
+/// futures are required to be `Send`.
+pub trait Backend {
+    /// Serve a single request.
+    fn dispatch_request<'a>(&'a self, req: Request) -> LocalBoxFuture<'a, Result<Response, BackendError>>;
+
+    /// Shut down the backend after the message queue has been closed.
+    fn shutdown(self) -> LocalBoxFuture<'static, Result<(), BackendError>>;
+}
 
-pub struct Backend {
+// The interval between two consecutive pings to the IndraDB server.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(2);
+// The time to wait for a ping or a tcp connection before treating the server as down.
+const CONN_TIMEOUT: Duration = Duration::from_secs(2);
+// The minimum and maximum delay between two reconnection attempts.
+const MIN_RECONNECT_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(10);
+// The time to wait for the response of a request before treating the server as down.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// A capnp rpc connection to the IndraDB server.
+struct Connection {
     tran_worker: crate::autogen::service::Client,
-    disconnector: capnp_rpc::Disconnector<Side>,
 }
 
-impl Backend {
-    pub fn new(client: crate::autogen::service::Client, disconnector: capnp_rpc::Disconnector<Side>) -> Self {
-        Self{
-            tran_worker: client, 
-            disconnector
+// The state of the connection that is shared between the backend and the keepalive task.
+enum ConnState {
+    Connected(Rc<Connection>, capnp_rpc::Disconnector<Side>),
+    // the connection is lost, the keepalive task is trying to reconnect
+    Disconnected,
+    // the backend has been shut down
+    Closed,
+}
+
+/// The backend that talks to an external IndraDB server through capnp rpc.
+/// 
+/// The backend pings the server periodically. When the connection is lost, 
+/// it keeps reconnecting to the server with exponential backoff, and all the requests 
+/// arriving in the meantime fail fast with `BackendErrorKind::Disconnected`.
+/// A request that is not answered in time fails with `BackendErrorKind::Disconnected` 
+/// as well, and the connection is treated as lost.
+pub struct CapnpBackend {
+    state: Rc<RefCell<ConnState>>,
+    request_timeout: Duration,
+}
+
+impl CapnpBackend {
+    /// Create a backend from an established connection to the IndraDB server at `addr`.
+    /// 
+    /// Must be called inside a `LocalSet`.
+    pub fn new(conn: TcpStream, addr: SocketAddr) -> Self {
+        Self::with_request_timeout(conn, addr, REQUEST_TIMEOUT)
+    }
+
+    fn with_request_timeout(conn: TcpStream, addr: SocketAddr, request_timeout: Duration) -> Self {
+        let (conn, disconnector) = Connection::start(conn);
+        let state = Rc::new(RefCell::new(ConnState::Connected(Rc::new(conn), disconnector)));
+        tokio::task::spawn_local(keepalive(addr, state.clone()));
+        
+        Self {state, request_timeout}
+    }
+
+    // retrieve the current connection, fail fast if the connection is lost
+    fn connection(&self) -> Result<Rc<Connection>, BackendError> {
+        match &*self.state.borrow() {
+            ConnState::Connected(conn, _) => Ok(conn.clone()),
+            _ => Err(BackendError::disconnected("connection to the database is lost, reconnecting".to_string())),
+        }
+    }
+
+    async fn dispatch(&self, req: Request) -> Result<Response, BackendError> {
+        let conn = self.connection()?;
+        let res = match time::timeout(self.request_timeout, conn.dispatch(req)).await {
+            Ok(res) => res,
+            Err(_) => Err(BackendError::disconnected("request to the database timeout".to_string())),
+        };
+
+        // hand over the lost connection to the keepalive task
+        if let Err(ref err) = res {
+            if let BackendErrorKind::Disconnected = err.kind() {
+                mark_disconnected(&self.state, &conn);
+            }
+        }
+        res
+    }
+}
+
+// Mark `conn` as lost if it is still the current connection.
+fn mark_disconnected(state: &RefCell<ConnState>, conn: &Rc<Connection>) {
+    let mut state = state.borrow_mut();
+    let is_current = match &*state {
+        ConnState::Connected(curr_conn, _) => Rc::ptr_eq(curr_conn, conn),
+        _ => false,
+    };
+    if is_current {
+        *state = ConnState::Disconnected;
+    }
+}
+
+// Periodically ping the server, and reconnect to the server once the connection is lost.
+async fn keepalive(addr: SocketAddr, state: Rc<RefCell<ConnState>>) {
+    loop {
+        time::delay_for(KEEPALIVE_INTERVAL).await;
+
+        let curr_conn = match &*state.borrow() {
+            ConnState::Connected(conn, _) => Some(conn.clone()),
+            ConnState::Disconnected => None,
+            ConnState::Closed => return,
+        };
+
+        match curr_conn {
+            Some(conn) => {
+                if !conn.is_alive().await {
+                    mark_disconnected(&state, &conn);
+                }
+            },
+            None => {
+                let (conn, disconnector) = match reconnect(addr, &state).await {
+                    Some(res) => res,
+                    None => return,
+                };
+                let mut state = state.borrow_mut();
+                if let ConnState::Closed = &*state {
+                    return;
+                }
+                *state = ConnState::Connected(Rc::new(conn), disconnector);
+            }
+        }
+    }
+}
+
+// Keep reconnecting to the server with exponential backoff, until the connection 
+// succeeds or the backend is shut down.
+async fn reconnect(addr: SocketAddr, state: &RefCell<ConnState>) -> Option<(Connection, capnp_rpc::Disconnector<Side>)> {
+    let mut backoff = MIN_RECONNECT_BACKOFF;
+    loop {
+        if let ConnState::Closed = &*state.borrow() {
+            return None;
         }
+
+        if let Ok(Ok(tcp_conn)) = time::timeout(CONN_TIMEOUT, TcpStream::connect(&addr)).await {
+            let (conn, disconnector) = Connection::start(tcp_conn);
+            if conn.is_alive().await {
+                return Some((conn, disconnector));
+            }
+        }
+
+        time::delay_for(backoff).await;
+        backoff = std::cmp::min(backoff * 2, MAX_RECONNECT_BACKOFF);
+    }
+}
+
+impl Connection {
+    // launch the rpc system on the tcp connection in the current `LocalSet`
+    fn start(conn: TcpStream) -> (Self, capnp_rpc::Disconnector<Side>) {
+        // create rpc_system
+        let (reader, writer) = tokio_util::compat::Tokio02AsyncReadCompatExt::compat(conn).split();
+        let rpc_network = Box::new(twoparty::VatNetwork::new(
+            reader,
+            writer,
+            Side::Client,
+            Default::default(),
+        ));
+        let mut capnp_rpc_system = RpcSystem::new(rpc_network, None);
+        
+        // create the capnp client
+        let tran_worker = capnp_rpc_system.bootstrap(Side::Server);
+        let disconnector = capnp_rpc_system.get_disconnector();
+
+        // run rpc_system
+        tokio::task::spawn_local(async move {
+            capnp_rpc_system.await
+        });
+
+        (Self {tran_worker}, disconnector)
+    }
+
+    // ping the server, report false if the server does not respond in time
+    async fn is_alive(&self) -> bool {
+        match time::timeout(CONN_TIMEOUT, self.ping()).await {
+            Ok(Ok(ready)) => ready,
+            _ => false,
+        }
+    }
+
+    async fn ping(&self) -> Result<bool, CapnpError> {
+        let req = self.tran_worker.ping_request();
+        let res = req.send().promise.await?;
+        Ok(res.get()?.get_ready())
     }
 }
 
@@ -40,11 +233,17 @@ macro_rules! transaction_wrapper {
     }
 }
 
-impl Backend {
+impl Connection {
     transaction_wrapper!(async_create_vertex, v: &Vertex, => bool);
     transaction_wrapper!(async_get_vertices, q: VertexQuery,  => Vec<Vertex>);
+    transaction_wrapper!(async_delete_vertices, q: VertexQuery,  => ());
     transaction_wrapper!(async_get_vertex_properties, q: VertexPropertyQuery, => Vec<VertexProperty>);
     transaction_wrapper!(async_set_vertex_properties, q: VertexPropertyQuery, value: &serde_json::Value, => ());
+    transaction_wrapper!(async_create_edge, e: &EdgeKey, => bool);
+    transaction_wrapper!(async_get_edges, q: EdgeQuery, => Vec<Edge>);
+    transaction_wrapper!(async_delete_edges, q: EdgeQuery, => ());
+    transaction_wrapper!(async_get_edge_properties, q: EdgePropertyQuery, => Vec<EdgeProperty>);
+    transaction_wrapper!(async_set_edge_properties, q: EdgePropertyQuery, value: &serde_json::Value, => ());
 
     async fn async_bulk_insert(&self, qs: Vec<BulkInsertItem>) -> Result<(), CapnpError> {
         let mut req = self.tran_worker.bulk_insert_request();
@@ -55,7 +254,7 @@ impl Backend {
         Ok(())
     }
 
-    async fn dispatch_request(&self, req: Request) -> Result<Response, BackendError> {
+    async fn dispatch(&self, req: Request) -> Result<Response, BackendError> {
         match req {
             Request::AsyncCreateVertex(v) => {
                 Ok(Response::AsyncCreateVertex(self.async_create_vertex(&v).await?))
@@ -63,12 +262,30 @@ impl Backend {
             Request::AsyncGetVertices(q) => {
                 Ok(Response::AsyncGetVertices(self.async_get_vertices(q).await?))
             },
+            Request::AsyncDeleteVertices(q) => {
+                Ok(Response::AsyncDeleteVertices(self.async_delete_vertices(q).await?))
+            },
             Request::AsyncGetVertexProperties(q) => {
                 Ok(Response::AsyncGetVertexProperties(self.async_get_vertex_properties(q).await?))
             },
             Request::AsyncSetVertexProperties(q, value) => {
                 Ok(Response::AsyncSetVertexProperties(self.async_set_vertex_properties(q, &value).await?))
             },
+            Request::AsyncCreateEdge(e) => {
+                Ok(Response::AsyncCreateEdge(self.async_create_edge(&e).await?))
+            },
+            Request::AsyncGetEdges(q) => {
+                Ok(Response::AsyncGetEdges(self.async_get_edges(q).await?))
+            },
+            Request::AsyncDeleteEdges(q) => {
+                Ok(Response::AsyncDeleteEdges(self.async_delete_edges(q).await?))
+            },
+            Request::AsyncGetEdgeProperties(q) => {
+                Ok(Response::AsyncGetEdgeProperties(self.async_get_edge_properties(q).await?))
+            },
+            Request::AsyncSetEdgeProperties(q, value) => {
+                Ok(Response::AsyncSetEdgeProperties(self.async_set_edge_properties(q, &value).await?))
+            },
             Request::AsyncBulkInsert(qs) => {
                 Ok(Response::AsyncBulkInsert(self.async_bulk_insert(qs).await?))
             }
@@ -76,27 +293,280 @@ impl Backend {
     }
 }
 
-pub fn build_backend_fut(backend: Backend, mut queue: Queue<Request, Response, BackendError>) 
+impl Backend for CapnpBackend {
+    fn dispatch_request<'a>(&'a self, req: Request) -> LocalBoxFuture<'a, Result<Response, BackendError>> {
+        Box::pin(self.dispatch(req))
+    }
+
+    fn shutdown(self) -> LocalBoxFuture<'static, Result<(), BackendError>> {
+        // stop the keepalive task and disconnect from the server
+        let prev_state = self.state.replace(ConnState::Closed);
+        Box::pin(async move {
+            match prev_state {
+                ConnState::Connected(_, disconnector) => disconnector.await.map_err(|e|{e.into()}),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+// Serve a single request and send back the response, report whether the request is a write.
+async fn serve_request<B: Backend>(backend: &B, req: Request, msg: Message<Request, Response, BackendError>, is_write: bool) -> bool {
+    let resp_result = backend.dispatch_request(req).await;
+    let _ = msg.callback(resp_result);
+    is_write
+}
+
+/// Build the future that serves the requests from `queue` with `backend`.
+/// 
+/// At most `max_in_flight` requests are served concurrently, counting the write requests 
+/// that wait for their turn, and 0 is treated as 1. The write requests are served one at 
+/// a time following their arrival order, so that they take effect in the order they are sent.
+pub fn build_backend_fut<B: Backend + 'static>(backend: B, mut queue: Queue<Request, Response, BackendError>, max_in_flight: usize) 
     -> impl Future<Output = Result<(), BackendError>> + 'static 
 {
-    fn drain_queue(mut queue: Queue<Request, Response, BackendError>) {
+    fn drain_queue(queue: &mut Queue<Request, Response, BackendError>) {
         queue.close();
         while let Ok(_) = queue.try_recv() {}
     }
     
-    async move {        
-        while let Some(mut msg) = queue.recv().await {
-            if msg.is_close_msg() {
-                drain_queue(queue);
-                break;
-            }
-            else {
-                let req = msg.try_get_msg().unwrap();
-                let resp_result = backend.dispatch_request(req).await;
-                let _ = msg.callback(resp_result);
+    let max_in_flight = std::cmp::max(max_in_flight, 1);
+    async move {
+        // the requests that are being served
+        let mut in_flight = FuturesUnordered::new();
+        // the write requests waiting for the preceding write request to finish
+        let mut pending_writes = VecDeque::new();
+        let mut write_in_flight = false;
+        let mut queue_closed = false;
+
+        loop {
+            // stop taking new requests while the served and waiting requests reach the limit
+            let accepting = !queue_closed && in_flight.len() + pending_writes.len() < max_in_flight;
+            tokio::select! {
+                res = queue.recv(), if accepting => {
+                    match res {
+                        Some(mut msg) => {
+                            if msg.is_close_msg() {
+                                drain_queue(&mut queue);
+                                queue_closed = true;
+                            }
+                            else {
+                                let req = msg.try_get_msg().unwrap();
+                                if !req.is_write() {
+                                    in_flight.push(serve_request(&backend, req, msg, false));
+                                }
+                                else if write_in_flight {
+                                    pending_writes.push_back((req, msg));
+                                }
+                                else {
+                                    write_in_flight = true;
+                                    in_flight.push(serve_request(&backend, req, msg, true));
+                                }
+                            }
+                        },
+                        None => {
+                            queue_closed = true;
+                        }
+                    }
+                },
+                Some(is_write) = in_flight.next(), if !in_flight.is_empty() => {
+                    if is_write {
+                        // start the next write request
+                        match pending_writes.pop_front() {
+                            Some((req, msg)) => in_flight.push(serve_request(&backend, req, msg, true)),
+                            None => write_in_flight = false,
+                        }
+                    }
+                },
+                else => break,
             }
         }
         
-        backend.disconnector.await.map_err(|e|{e.into()})
+        drop(in_flight);
+        backend.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use futures::future::join_all;
+    use indradb::{SpecificVertexQuery, Type};
+    use tokio::task::LocalSet;
+    use uuid::Uuid;
+
+    use super::*;
+    use super::super::message_queue;
+
+    const DELAY: Duration = Duration::from_millis(20);
+
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    enum Event {
+        Start(u128),
+        End(u128),
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<Event>,
+        in_flight: usize,
+        max_in_flight: usize,
+    }
+
+    // a backend that takes `DELAY` to serve each request, and records when each request 
+    // starts and finishes
+    struct DelayBackend {
+        recorder: Rc<RefCell<Recorder>>,
+    }
+
+    impl Backend for DelayBackend {
+        fn dispatch_request<'a>(&'a self, req: Request) -> LocalBoxFuture<'a, Result<Response, BackendError>> {
+            Box::pin(async move {
+                let (tag, resp) = match req {
+                    Request::AsyncCreateVertex(v) => (v.id.as_u128(), Response::AsyncCreateVertex(true)),
+                    Request::AsyncGetVertices(VertexQuery::Specific(q)) => (q.ids[0].as_u128(), Response::AsyncGetVertices(Vec::new())),
+                    _ => panic!("unexpected request"),
+                };
+                {
+                    let mut recorder = self.recorder.borrow_mut();
+                    recorder.events.push(Event::Start(tag));
+                    recorder.in_flight += 1;
+                    recorder.max_in_flight = std::cmp::max(recorder.max_in_flight, recorder.in_flight);
+                }
+                time::delay_for(DELAY).await;
+                let mut recorder = self.recorder.borrow_mut();
+                recorder.events.push(Event::End(tag));
+                recorder.in_flight -= 1;
+                Ok(resp)
+            })
+        }
+
+        fn shutdown(self) -> LocalBoxFuture<'static, Result<(), BackendError>> {
+            Box::pin(async {Ok(())})
+        }
+    }
+
+    fn write(tag: u128) -> Request {
+        Request::AsyncCreateVertex(Vertex::with_id(Uuid::from_u128(tag), Type::new("t").unwrap()))
+    }
+
+    fn read(tag: u128) -> Request {
+        Request::AsyncGetVertices(SpecificVertexQuery::single(Uuid::from_u128(tag)).into())
+    }
+
+    type Sender = message_queue::Sender<Request, Response, BackendError>;
+
+    // run `f` with a sender to the backend future serving at most `max_in_flight` requests,
+    // and return the record of the backend once all the requests are served
+    async fn serve<F, Fut>(max_in_flight: usize, f: F) -> Recorder 
+    where
+        F: FnOnce(Sender) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+        let backend = DelayBackend { recorder: recorder.clone() };
+        LocalSet::new().run_until(async move {
+            let (sender, queue) = message_queue::create();
+            let backend_fut = tokio::task::spawn_local(build_backend_fut(backend, queue, max_in_flight));
+            f(sender).await;
+            backend_fut.await.unwrap().unwrap();
+        }).await;
+        Rc::try_unwrap(recorder).ok().unwrap().into_inner()
+    }
+
+    fn position(events: &Vec<Event>, event: Event) -> usize {
+        events.iter().position(|e| {*e == event}).unwrap()
+    }
+
+    #[tokio::test]
+    async fn writes_are_served_in_arrival_order() {
+        let recorder = serve(8, |sender| async move {
+            // the requests arrive in the order they are polled
+            let reqs = vec!(write(1), read(10), write(2), read(11), write(3));
+            for res in join_all(reqs.into_iter().map(|req| {sender.send(req)})).await.into_iter() {
+                res.unwrap();
+            }
+        }).await;
+
+        let writes: Vec<Event> = recorder.events.iter().cloned().filter(|e| {
+            match e {
+                Event::Start(tag) | Event::End(tag) => *tag < 10,
+            }
+        }).collect();
+        assert_eq!(writes, vec!(
+            Event::Start(1), Event::End(1), Event::Start(2), Event::End(2), Event::Start(3), Event::End(3)
+        ));
+        // the reads are not held back by the writes
+        assert!(position(&recorder.events, Event::Start(11)) < position(&recorder.events, Event::End(1)));
+    }
+
+    #[tokio::test]
+    async fn read_follows_write_of_same_caller() {
+        let recorder = serve(8, |sender| async move {
+            let other = sender.clone();
+            let caller = async move {
+                sender.send(write(1)).await.unwrap();
+                sender.send(read(10)).await.unwrap();
+            };
+            // the write of the caller waits for the write of the other caller
+            let other = async move {
+                other.send(write(2)).await.unwrap();
+            };
+            futures::future::join(other, caller).await;
+        }).await;
+
+        let events = &recorder.events;
+        assert!(position(events, Event::End(2)) < position(events, Event::Start(1)));
+        assert!(position(events, Event::End(1)) < position(events, Event::Start(10)));
+    }
+
+    #[tokio::test]
+    async fn in_flight_requests_are_limited() {
+        for (limit, expected) in vec!((3, 3), (0, 1), (16, 10)).into_iter() {
+            let recorder = serve(limit, |sender| async move {
+                let reqs: Vec<Request> = (0..10).map(|tag| {read(tag)}).collect();
+                for res in join_all(reqs.into_iter().map(|req| {sender.send(req)})).await.into_iter() {
+                    res.unwrap();
+                }
+            }).await;
+            assert_eq!(recorder.events.len(), 20);
+            assert_eq!(recorder.max_in_flight, expected);
+        }
+
+        // the writes waiting for their turn count against the limit as well
+        let recorder = serve(3, |sender| async move {
+            let reqs = vec!(write(1), write(2), write(3), read(10));
+            for res in join_all(reqs.into_iter().map(|req| {sender.send(req)})).await.into_iter() {
+                res.unwrap();
+            }
+        }).await;
+        assert!(position(&recorder.events, Event::End(1)) < position(&recorder.events, Event::Start(10)));
+    }
+
+    #[tokio::test]
+    async fn unanswered_request_fails_with_disconnected() {
+        let mut listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        LocalSet::new().run_until(async move {
+            let (conn, accepted) = futures::future::join(TcpStream::connect(&addr), listener.accept()).await;
+            // the server accepts the connection but never answers
+            let _server_conn = accepted.unwrap();
+            let timeout = Duration::from_millis(100);
+            let backend = CapnpBackend::with_request_timeout(conn.unwrap(), addr, timeout);
+
+            let start = time::Instant::now();
+            let err = backend.dispatch_request(read(0)).await.err().unwrap();
+            assert!(err.is_retryable());
+            assert!(start.elapsed() >= timeout);
+
+            // the following requests fail fast until the connection is re-established
+            let start = time::Instant::now();
+            let err = backend.dispatch_request(read(1)).await.err().unwrap();
+            assert!(err.is_retryable());
+            assert!(start.elapsed() < timeout);
+            backend.shutdown().await.unwrap();
+        }).await;
     }
 }
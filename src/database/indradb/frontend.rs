@@ -1,33 +1,49 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use indradb::{Vertex, VertexQuery};
 use indradb::{VertexProperty, VertexPropertyQuery};
 use indradb::BulkInsertItem;
 use indradb::{SpecificVertexQuery, VertexQueryExt, RangeVertexQuery};
+use indradb::{Edge, EdgeKey, EdgeQuery};
+use indradb::{EdgeProperty, EdgePropertyQuery};
+use indradb::{SpecificEdgeQuery, EdgeQueryExt, PipeEdgeQuery};
 use indradb::Type;
 use uuid::Uuid;
 use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::Mutex;
 
 use crate::database::errors::BackendError;
-use crate::emunet::{server, user};
+use crate::emunet::{server, user, net};
+use crate::emunet::link_plan::VniPool;
 use crate::database::CORE_INFO_ID;
 use super::message_queue;
 use super::message::{Request, Response};
 
 pub struct Frontend {
     sender: message_queue::Sender<Request, Response, BackendError>,
+    // serializes the read-modify-write updates of the core properties
+    core_lock: Arc<Mutex<()>>,
+    // serializes the read-modify-write updates of the emunet objects
+    emunet_lock: Arc<Mutex<()>>,
 }
 
 impl Frontend {
     pub fn new(sender: message_queue::Sender<Request, Response, BackendError>) -> Self {
-        Self {sender}
+        Self {
+            sender,
+            core_lock: Arc::new(Mutex::new(())),
+            emunet_lock: Arc::new(Mutex::new(())),
+        }
     }
 }
 
 impl Clone for Frontend {
     fn clone(&self) -> Self {
         Self {
-            sender: self.sender.clone()
+            sender: self.sender.clone(),
+            core_lock: self.core_lock.clone(),
+            emunet_lock: self.emunet_lock.clone(),
         }
     }
 }
@@ -52,8 +68,14 @@ macro_rules! request_wrapper {
 impl Frontend {
     request_wrapper!(async_create_vertex, AsyncCreateVertex, v: Vertex, => bool);    
     request_wrapper!(async_get_vertices, AsyncGetVertices, q: VertexQuery,  => Vec<Vertex>);
+    request_wrapper!(async_delete_vertices, AsyncDeleteVertices, q: VertexQuery,  => ());
     request_wrapper!(async_get_vertex_properties, AsyncGetVertexProperties, q: VertexPropertyQuery, => Vec<VertexProperty>);
     request_wrapper!(async_set_vertex_properties, AsyncSetVertexProperties, q: VertexPropertyQuery, value: serde_json::Value, => ());
+    request_wrapper!(async_create_edge, AsyncCreateEdge, e: EdgeKey, => bool);
+    request_wrapper!(async_get_edges, AsyncGetEdges, q: EdgeQuery, => Vec<Edge>);
+    request_wrapper!(async_delete_edges, AsyncDeleteEdges, q: EdgeQuery, => ());
+    request_wrapper!(async_get_edge_properties, AsyncGetEdgeProperties, q: EdgePropertyQuery, => Vec<EdgeProperty>);
+    request_wrapper!(async_set_edge_properties, AsyncSetEdgeProperties, q: EdgePropertyQuery, value: serde_json::Value, => ());
 }
 
 impl Frontend {
@@ -104,6 +126,39 @@ impl Frontend {
         Ok(true)
     }
 
+    // create a directed edge, return false if any of the two vertexes does not exist
+    pub async fn create_edge(&self, key: EdgeKey) -> Result<bool, BackendError> {
+        self.async_create_edge(key).await
+    }
+
+    // get all the edges specified by the query
+    pub async fn get_edges(&self, q: EdgeQuery) -> Result<Vec<Edge>, BackendError> {
+        self.async_get_edges(q).await
+    }
+
+    // delete all the edges specified by the query, together with their properties
+    pub async fn delete_edges(&self, q: EdgeQuery) -> Result<(), BackendError> {
+        self.async_delete_edges(q).await
+    }
+
+    // set json property with name `property_name` for edge with key `key`
+    pub async fn set_edge_json_value(&self, key: EdgeKey, property_name: &str, json: serde_json::Value) -> Result<bool, BackendError> {
+        let q: EdgeQuery = SpecificEdgeQuery::single(key).into();
+        let edge_list = self.async_get_edges(q).await?;
+        if edge_list.len() == 0 {
+            return Ok(false);
+        }
+
+        let q = SpecificEdgeQuery::new(edge_list.into_iter().map(|e|{e.key}).collect()).property(property_name);
+        self.async_set_edge_properties(q, json).await?;
+        Ok(true)
+    }
+
+    // delete all the vertexes specified by the query, together with their properties and edges
+    pub async fn delete_vertices(&self, q: VertexQuery) -> Result<(), BackendError> {
+        self.async_delete_vertices(q).await
+    }
+
     // perform a bulk insertion
     pub async fn bulk_insert(&self, qs: Vec<BulkInsertItem>) -> Result<(), BackendError> {
         let res = self.sender.send(Request::AsyncBulkInsert(qs)).await?;
@@ -123,6 +178,17 @@ impl Frontend {
             })
         })
     }
+
+    // get all the edges
+    pub async fn get_edge_properties(&self, q: PipeEdgeQuery) -> Result<Vec<serde_json::Value>, BackendError> {
+        let q = q.property("default".to_string());
+        self.async_get_edge_properties(q).await.map(|ep| {
+            ep.into_iter().fold(Vec::new(), |mut vec, ep| {
+                vec.push(ep.value);
+                vec
+            })
+        })
+    }
 }
 
 impl Frontend {
@@ -145,6 +211,109 @@ impl Frontend {
     }
 }
 
+impl Frontend {
+    // public interfaces for atomically updating core information:
+    // 
+    // All the updates share the same lock, so that the read-modify-write sequences
+    // issued through all the clones of the frontend are serialized. 
+    // The closure modifies the stored value in place. The modified value is written 
+    // back to the database only if the closure returns Ok, and the result of the 
+    // closure is handed back to the caller.
+    // 
+    // Note: the closure must not call any of the update methods, otherwise the 
+    // update deadlocks.
+    pub async fn update_user_map<F, R, E>(&self, f: F) -> Result<Result<R, E>, BackendError>
+    where
+        F: FnOnce(&mut HashMap<String, user::EmuNetUser>) -> Result<R, E>
+    {
+        let _guard = self.core_lock.lock().await;
+        
+        let mut user_map = self.get_user_map().await?;
+        let res = f(&mut user_map);
+        if res.is_ok() {
+            self.set_user_map(user_map).await?;
+        }
+        Ok(res)
+    }
+
+    pub async fn update_server_info_list<F, R, E>(&self, f: F) -> Result<Result<R, E>, BackendError>
+    where
+        F: FnOnce(&mut Vec<server::ServerInfo>) -> Result<R, E>
+    {
+        let _guard = self.core_lock.lock().await;
+        
+        let mut server_info_list = self.get_server_info_list().await?;
+        let res = f(&mut server_info_list);
+        if res.is_ok() {
+            self.set_server_info_list(server_info_list).await?;
+        }
+        Ok(res)
+    }
+
+    pub async fn update_vni_pool<F, R, E>(&self, f: F) -> Result<Result<R, E>, BackendError>
+    where
+        F: FnOnce(&mut VniPool) -> Result<R, E>
+    {
+        let _guard = self.core_lock.lock().await;
+        
+        let mut vni_pool = self.get_vni_pool().await?;
+        let res = f(&mut vni_pool);
+        if res.is_ok() {
+            self.set_vni_pool(vni_pool).await?;
+        }
+        Ok(res)
+    }
+
+    pub async fn update_core_info<F, R, E>(&self, f: F) -> Result<Result<R, E>, BackendError>
+    where
+        F: FnOnce(&mut HashMap<String, user::EmuNetUser>, &mut Vec<server::ServerInfo>) -> Result<R, E>
+    {
+        let _guard = self.core_lock.lock().await;
+        
+        let mut user_map = self.get_user_map().await?;
+        let mut server_info_list = self.get_server_info_list().await?;
+        let res = f(&mut user_map, &mut server_info_list);
+        if res.is_ok() {
+            self.set_server_info_list(server_info_list).await?;
+            self.set_user_map(user_map).await?;
+        }
+        Ok(res)
+    }
+}
+
+impl Frontend {
+    // public interfaces for updating the emunet objects:
+    //
+    // The emunet objects are written under the same lock, so that an update does not 
+    // overwrite a concurrent one. Return None if the emunet does not exist.
+    pub async fn update_emu_net<F, R, E>(&self, uuid: Uuid, f: F) -> Result<Option<Result<R, E>>, BackendError>
+    where
+        F: FnOnce(&mut net::EmuNet) -> Result<R, E>
+    {
+        let _guard = self.emunet_lock.lock().await;
+
+        let mut emunet: net::EmuNet = match self.get_vertex_json_value(uuid, "default").await? {
+            Some(jv) => serde_json::from_value(jv).unwrap(),
+            None => return Ok(None),
+        };
+        let res = f(&mut emunet);
+        if res.is_ok() {
+            let jv = serde_json::to_value(emunet).unwrap();
+            self.set_vertex_json_value(uuid, "default", jv).await?;
+        }
+        Ok(Some(res))
+    }
+
+    // return false if the emunet does not exist
+    pub async fn set_emu_net(&self, emunet: net::EmuNet) -> Result<bool, BackendError> {
+        let _guard = self.emunet_lock.lock().await;
+
+        let uuid = emunet.uuid().clone();
+        let jv = serde_json::to_value(emunet).unwrap();
+        self.set_vertex_json_value(uuid, "default", jv).await
+    }
+}
+
 impl Frontend {
     // public interfaces for accessing core information
     pub async fn get_server_info_list(&self) -> Result<Vec<server::ServerInfo>, BackendError> {
@@ -162,4 +331,14 @@ impl Frontend {
     pub async fn set_user_map(&self, user_map: HashMap<String, user::EmuNetUser>) -> Result<(), BackendError> {
         self.set_core_property("user_map", user_map).await
     }
+
+    // the databases initialized before the link planning have no vni pool
+    pub async fn get_vni_pool(&self) -> Result<VniPool, BackendError> {
+        let res = self.get_vertex_json_value(CORE_INFO_ID.clone(), "vni_pool").await?;
+        Ok(res.map(|jv| {serde_json::from_value(jv).unwrap()}).unwrap_or_default())
+    }
+
+    pub async fn set_vni_pool(&self, vni_pool: VniPool) -> Result<(), BackendError> {
+        self.set_core_property("vni_pool", vni_pool).await
+    }
 }
\ No newline at end of file
@@ -0,0 +1,72 @@
+// An in-process storage backend built on top of indradb::MemoryDatastore
+use futures::future::{self, LocalBoxFuture};
+use indradb::{Datastore, Transaction, MemoryDatastore};
+
+use super::backend::Backend;
+use super::message::{Request, Response};
+use crate::database::errors::BackendError;
+
+/// The backend that stores everything in an in-process IndraDB memory datastore.
+///
+/// It requires no external database server, and all the stored data are lost
+/// when the backend is shut down.
+pub struct MemoryBackend {
+    datastore: MemoryDatastore,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self {
+            datastore: MemoryDatastore::default(),
+        }
+    }
+
+    fn dispatch(&self, req: Request) -> Result<Response, indradb::Error> {
+        let trans = self.datastore.transaction()?;
+        match req {
+            Request::AsyncCreateVertex(v) => {
+                Ok(Response::AsyncCreateVertex(trans.create_vertex(&v)?))
+            },
+            Request::AsyncGetVertices(q) => {
+                Ok(Response::AsyncGetVertices(trans.get_vertices(q)?))
+            },
+            Request::AsyncDeleteVertices(q) => {
+                Ok(Response::AsyncDeleteVertices(trans.delete_vertices(q)?))
+            },
+            Request::AsyncGetVertexProperties(q) => {
+                Ok(Response::AsyncGetVertexProperties(trans.get_vertex_properties(q)?))
+            },
+            Request::AsyncSetVertexProperties(q, value) => {
+                Ok(Response::AsyncSetVertexProperties(trans.set_vertex_properties(q, &value)?))
+            },
+            Request::AsyncCreateEdge(e) => {
+                Ok(Response::AsyncCreateEdge(trans.create_edge(&e)?))
+            },
+            Request::AsyncGetEdges(q) => {
+                Ok(Response::AsyncGetEdges(trans.get_edges(q)?))
+            },
+            Request::AsyncDeleteEdges(q) => {
+                Ok(Response::AsyncDeleteEdges(trans.delete_edges(q)?))
+            },
+            Request::AsyncGetEdgeProperties(q) => {
+                Ok(Response::AsyncGetEdgeProperties(trans.get_edge_properties(q)?))
+            },
+            Request::AsyncSetEdgeProperties(q, value) => {
+                Ok(Response::AsyncSetEdgeProperties(trans.set_edge_properties(q, &value)?))
+            },
+            Request::AsyncBulkInsert(qs) => {
+                Ok(Response::AsyncBulkInsert(self.datastore.bulk_insert(qs.into_iter())?))
+            }
+        }
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn dispatch_request<'a>(&'a self, req: Request) -> LocalBoxFuture<'a, Result<Response, BackendError>> {
+        Box::pin(future::ready(self.dispatch(req).map_err(|e|{e.into()})))
+    }
+
+    fn shutdown(self) -> LocalBoxFuture<'static, Result<(), BackendError>> {
+        Box::pin(future::ready(Ok(())))
+    }
+}
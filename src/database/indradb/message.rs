@@ -1,21 +1,51 @@
 use indradb::{Vertex, VertexQuery};
 use indradb::{VertexProperty, VertexPropertyQuery};
+use indradb::{Edge, EdgeKey, EdgeQuery};
+use indradb::{EdgeProperty, EdgePropertyQuery};
 use indradb::BulkInsertItem;
 
 #[derive(Clone)]
 pub enum Request {
     AsyncCreateVertex(Vertex),
     AsyncGetVertices(VertexQuery),
+    AsyncDeleteVertices(VertexQuery),
     AsyncGetVertexProperties(VertexPropertyQuery),
     AsyncSetVertexProperties(VertexPropertyQuery, serde_json::Value),
+    AsyncCreateEdge(EdgeKey),
+    AsyncGetEdges(EdgeQuery),
+    AsyncDeleteEdges(EdgeQuery),
+    AsyncGetEdgeProperties(EdgePropertyQuery),
+    AsyncSetEdgeProperties(EdgePropertyQuery, serde_json::Value),
     AsyncBulkInsert(Vec<BulkInsertItem>),
 }
 
+impl Request {
+    // Whether the request modifies the database.
+    // 
+    // The modifying requests are served one at a time following their arrival order, 
+    // while the rest of the requests are served concurrently.
+    pub fn is_write(&self) -> bool {
+        match self {
+            Request::AsyncGetVertices(_) => false,
+            Request::AsyncGetVertexProperties(_) => false,
+            Request::AsyncGetEdges(_) => false,
+            Request::AsyncGetEdgeProperties(_) => false,
+            _ => true,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum Response {
     AsyncCreateVertex(bool),
     AsyncGetVertices(Vec<Vertex>),
+    AsyncDeleteVertices(()),
     AsyncGetVertexProperties(Vec<VertexProperty>),
     AsyncSetVertexProperties(()),
+    AsyncCreateEdge(bool),
+    AsyncGetEdges(Vec<Edge>),
+    AsyncDeleteEdges(()),
+    AsyncGetEdgeProperties(Vec<EdgeProperty>),
+    AsyncSetEdgeProperties(()),
     AsyncBulkInsert(()),
 }
\ No newline at end of file
@@ -1,9 +1,11 @@
 pub mod indradb_util;
 
 mod backend;
+mod memory_backend;
 mod frontend;
 mod message; 
 pub mod message_queue;
 
-pub use backend::{Backend, build_backend_fut};
+pub use backend::{Backend, CapnpBackend, build_backend_fut};
+pub use memory_backend::MemoryBackend;
 pub use frontend::Frontend;
\ No newline at end of file
@@ -0,0 +1,318 @@
+// Allocation of the IPv4 addresses of the links and devices in an emunet.
+//
+// Each link takes a /30 or /31 subnet from the link pool, and each device takes a /32
+// loopback address from the loopback pool. The addresses of the container servers are
+// never allocated.
+use std::collections::BTreeSet;
+use std::net::Ipv4Addr;
+
+use serde::{Deserialize, Serialize};
+
+fn default_link_pool() -> String {
+    "10.0.0.0/16".to_string()
+}
+
+fn default_link_prefix() -> u8 {
+    30
+}
+
+fn default_loopback_pool() -> String {
+    "10.255.0.0/16".to_string()
+}
+
+/// The address pools of an emunet, in CIDR notation.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct IpamConfig {
+    #[serde(default = "default_link_pool")]
+    pub link_pool: String,
+    /// The prefix length of the subnet of each link, either 30 or 31.
+    #[serde(default = "default_link_prefix")]
+    pub link_prefix: u8,
+    #[serde(default = "default_loopback_pool")]
+    pub loopback_pool: String,
+}
+
+impl Default for IpamConfig {
+    fn default() -> Self {
+        Self {
+            link_pool: default_link_pool(),
+            link_prefix: default_link_prefix(),
+            loopback_pool: default_loopback_pool(),
+        }
+    }
+}
+
+// Parse "a.b.c.d/len" into the network address and the prefix length.
+fn parse_cidr(s: &str) -> Result<(u32, u8), String> {
+    let mut parts = s.splitn(2, '/');
+    let addr: Ipv4Addr = parts.next().unwrap().parse().map_err(|_| {format!("invalid pool {}", s)})?;
+    let prefix_len: u8 = parts.next().ok_or(format!("invalid pool {}", s))?.parse().map_err(|_| {format!("invalid pool {}", s)})?;
+    if prefix_len > 32 {
+        return Err(format!("invalid pool {}", s));
+    }
+    let addr = u32::from(addr);
+    if prefix_len < 32 && addr & (u32::MAX >> prefix_len) != 0 {
+        return Err(format!("pool {} has host bits set", s));
+    }
+    Ok((addr, prefix_len))
+}
+
+/// The addresses of the two ends of a link.
+///
+/// `addrs.0` is assigned to the device at `edge_uuid.0` of the link.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct LinkAddrs {
+    pub prefix_len: u8,
+    pub addrs: (Ipv4Addr, Ipv4Addr),
+}
+
+// A pool of equally sized address blocks.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct BlockPool {
+    base: u32, // network address of the pool
+    prefix_len: u8, // prefix length of the pool
+    block_len: u8, // prefix length of each block
+    used: BTreeSet<u32>, // indexes of the allocated blocks
+    next: u32, // index of the block where the search for a free block starts
+}
+
+impl BlockPool {
+    fn new(pool: &str, block_len: u8) -> Result<Self, String> {
+        let (base, prefix_len) = parse_cidr(pool)?;
+        if prefix_len > block_len {
+            return Err(format!("pool {} is smaller than a /{} block", pool, block_len));
+        }
+        Ok(Self {
+            base,
+            prefix_len,
+            block_len,
+            used: BTreeSet::new(),
+            next: 0,
+        })
+    }
+
+    fn num_blocks(&self) -> u64 {
+        1u64 << (self.block_len - self.prefix_len)
+    }
+
+    fn block_size(&self) -> u64 {
+        1u64 << (32 - self.block_len)
+    }
+
+    fn block_addr(&self, idx: u32) -> u32 {
+        self.base + (idx as u64 * self.block_size()) as u32
+    }
+
+    fn contains(&self, addr: u32) -> bool {
+        let mask = if self.prefix_len == 0 { 0 } else { u32::MAX << (32 - self.prefix_len) };
+        addr & mask == self.base
+    }
+
+    fn remaining(&self) -> u64 {
+        self.num_blocks() - self.used.len() as u64
+    }
+
+    // Allocate the first free block after the previously allocated one, skipping the
+    // blocks that hold any of the `reserved` addresses.
+    fn alloc(&mut self, reserved: &Vec<u32>) -> Option<u32> {
+        let num_blocks = self.num_blocks();
+        for i in 0..num_blocks {
+            let idx = ((self.next as u64 + i) % num_blocks) as u32;
+            if self.used.contains(&idx) {
+                continue;
+            }
+            let addr = self.block_addr(idx);
+            if reserved.iter().any(|r| {*r >= addr && (*r - addr) as u64 <= self.block_size() - 1}) {
+                continue;
+            }
+            self.used.insert(idx);
+            self.next = ((idx as u64 + 1) % num_blocks) as u32;
+            return Some(addr);
+        }
+        None
+    }
+
+    fn release(&mut self, addr: u32) {
+        if self.contains(addr) {
+            self.used.remove(&(((addr - self.base) as u64 / self.block_size()) as u32));
+        }
+    }
+}
+
+/// The address allocation of an emunet.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Ipam {
+    links: BlockPool,
+    loopbacks: BlockPool,
+    reserved: Vec<u32>, // the addresses of the container servers
+}
+
+impl Ipam {
+    /// Create the pools from `config`, the `reserved` addresses are never allocated.
+    pub fn new(config: &IpamConfig, reserved: Vec<Ipv4Addr>) -> Result<Self, String> {
+        if config.link_prefix != 30 && config.link_prefix != 31 {
+            return Err(format!("invalid link prefix length {}, which should be 30 or 31", config.link_prefix));
+        }
+        let links = BlockPool::new(&config.link_pool, config.link_prefix)?;
+        let loopbacks = BlockPool::new(&config.loopback_pool, 32)?;
+        if links.contains(loopbacks.base) || loopbacks.contains(links.base) {
+            return Err(format!("link pool {} overlaps with loopback pool {}", &config.link_pool, &config.loopback_pool));
+        }
+
+        Ok(Self {
+            links,
+            loopbacks,
+            reserved: reserved.into_iter().map(|addr| {u32::from(addr)}).collect(),
+        })
+    }
+
+    /// Check that the pools can hold `links` more links and `devs` more devices.
+    pub fn check_capacity(&self, links: usize, devs: usize) -> Result<(), String> {
+        // a reserved address takes at most a block from each pool
+        let reserved = self.reserved.len() as u64;
+        if links as u64 + reserved > self.links.remaining() {
+            return Err("not enough addresses in the link pool".to_string());
+        }
+        if devs as u64 + reserved > self.loopbacks.remaining() {
+            return Err("not enough addresses in the loopback pool".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn alloc_link(&mut self) -> Result<LinkAddrs, String> {
+        let addr = self.links.alloc(&self.reserved).ok_or("link pool is exhausted".to_string())?;
+        // a /31 subnet has no network and broadcast addresses
+        let first = if self.links.block_len == 31 { addr } else { addr + 1 };
+        Ok(LinkAddrs {
+            prefix_len: self.links.block_len,
+            addrs: (Ipv4Addr::from(first), Ipv4Addr::from(first + 1)),
+        })
+    }
+
+    pub fn release_link(&mut self, link_addrs: &LinkAddrs) {
+        self.links.release(u32::from(link_addrs.addrs.0));
+    }
+
+    pub fn alloc_loopback(&mut self) -> Result<Ipv4Addr, String> {
+        let addr = self.loopbacks.alloc(&self.reserved).ok_or("loopback pool is exhausted".to_string())?;
+        Ok(Ipv4Addr::from(addr))
+    }
+
+    pub fn release_loopback(&mut self, addr: Ipv4Addr) {
+        self.loopbacks.release(u32::from(addr));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_ipam(link_pool: &str, link_prefix: u8, loopback_pool: &str, reserved: Vec<&str>) -> Result<Ipam, String> {
+        let config = IpamConfig {
+            link_pool: link_pool.to_string(),
+            link_prefix,
+            loopback_pool: loopback_pool.to_string(),
+        };
+        Ipam::new(&config, reserved.into_iter().map(|addr| {addr.parse().unwrap()}).collect())
+    }
+
+    fn addrs(link_addrs: LinkAddrs) -> (String, String) {
+        (link_addrs.addrs.0.to_string(), link_addrs.addrs.1.to_string())
+    }
+
+    #[test]
+    fn block_addr_covers_the_whole_address_space() {
+        let pool = BlockPool::new("0.0.0.0/0", 32).unwrap();
+        assert_eq!(pool.num_blocks(), 1u64 << 32);
+        assert_eq!(pool.block_addr(u32::MAX), u32::MAX);
+        assert_eq!(pool.remaining(), 1u64 << 32);
+
+        let pool = BlockPool::new("0.0.0.0/0", 30).unwrap();
+        assert_eq!(pool.block_addr((1 << 30) - 1), u32::from(Ipv4Addr::new(255, 255, 255, 252)));
+
+        let pool = BlockPool::new("255.255.255.252/30", 31).unwrap();
+        assert_eq!(pool.block_addr(1), u32::from(Ipv4Addr::new(255, 255, 255, 254)));
+    }
+
+    #[test]
+    fn link_hosts_depend_on_prefix() {
+        let mut ipam = new_ipam("10.0.0.0/29", 30, "10.255.0.0/16", vec!()).unwrap();
+        let link = ipam.alloc_link().unwrap();
+        assert_eq!(link.prefix_len, 30);
+        assert_eq!(addrs(link), ("10.0.0.1".to_string(), "10.0.0.2".to_string()));
+        assert_eq!(addrs(ipam.alloc_link().unwrap()), ("10.0.0.5".to_string(), "10.0.0.6".to_string()));
+
+        // a /31 subnet uses both of its addresses
+        let mut ipam = new_ipam("10.0.0.0/29", 31, "10.255.0.0/16", vec!()).unwrap();
+        let link = ipam.alloc_link().unwrap();
+        assert_eq!(link.prefix_len, 31);
+        assert_eq!(addrs(link), ("10.0.0.0".to_string(), "10.0.0.1".to_string()));
+        assert_eq!(addrs(ipam.alloc_link().unwrap()), ("10.0.0.2".to_string(), "10.0.0.3".to_string()));
+    }
+
+    #[test]
+    fn blocks_with_reserved_addresses_are_skipped() {
+        let mut ipam = new_ipam("10.0.0.0/29", 30, "10.255.0.0/30", vec!("10.0.0.3", "10.255.0.0")).unwrap();
+        assert_eq!(addrs(ipam.alloc_link().unwrap()), ("10.0.0.5".to_string(), "10.0.0.6".to_string()));
+        assert_eq!(ipam.alloc_link().unwrap_err(), "link pool is exhausted");
+        assert_eq!(ipam.alloc_loopback().unwrap(), Ipv4Addr::new(10, 255, 0, 1));
+    }
+
+    #[test]
+    fn search_wraps_around() {
+        let mut ipam = new_ipam("10.0.0.0/28", 30, "10.255.0.0/16", vec!()).unwrap();
+        let first = ipam.alloc_link().unwrap();
+        assert_eq!(addrs(ipam.alloc_link().unwrap()).0, "10.0.0.5");
+
+        // a released block is taken again only after the search wraps around
+        ipam.release_link(&first);
+        assert_eq!(addrs(ipam.alloc_link().unwrap()).0, "10.0.0.9");
+        assert_eq!(addrs(ipam.alloc_link().unwrap()).0, "10.0.0.13");
+        assert_eq!(addrs(ipam.alloc_link().unwrap()).0, "10.0.0.1");
+        assert_eq!(ipam.alloc_link().unwrap_err(), "link pool is exhausted");
+    }
+
+    #[test]
+    fn foreign_addresses_are_not_released() {
+        let mut ipam = new_ipam("10.0.0.0/29", 30, "10.255.0.0/31", vec!()).unwrap();
+        ipam.alloc_link().unwrap();
+        ipam.alloc_loopback().unwrap();
+        let foreign = LinkAddrs {
+            prefix_len: 30,
+            addrs: (Ipv4Addr::new(10, 1, 0, 1), Ipv4Addr::new(10, 1, 0, 2)),
+        };
+        ipam.release_link(&foreign);
+        ipam.release_loopback(Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(ipam.links.remaining(), 1);
+        assert_eq!(ipam.loopbacks.remaining(), 1);
+
+        ipam.release_loopback(Ipv4Addr::new(10, 255, 0, 0));
+        assert_eq!(ipam.loopbacks.remaining(), 2);
+    }
+
+    #[test]
+    fn invalid_pools_are_refused() {
+        assert_eq!(
+            new_ipam("10.0.0.0/8", 30, "10.255.0.0/16", vec!()).err().unwrap(), 
+            "link pool 10.0.0.0/8 overlaps with loopback pool 10.255.0.0/16"
+        );
+        assert_eq!(
+            new_ipam("10.1.0.0/16", 30, "10.0.0.0/8", vec!()).err().unwrap(), 
+            "link pool 10.1.0.0/16 overlaps with loopback pool 10.0.0.0/8"
+        );
+        assert!(new_ipam("10.0.0.0/16", 30, "10.1.0.0/16", vec!()).is_ok());
+        assert_eq!(new_ipam("10.0.0.0/16", 29, "10.255.0.0/16", vec!()).err().unwrap(), "invalid link prefix length 29, which should be 30 or 31");
+        assert_eq!(new_ipam("10.0.0.1/16", 30, "10.255.0.0/16", vec!()).err().unwrap(), "pool 10.0.0.1/16 has host bits set");
+        assert_eq!(new_ipam("10.0.0.0/31", 30, "10.255.0.0/16", vec!()).err().unwrap(), "pool 10.0.0.0/31 is smaller than a /30 block");
+        assert_eq!(new_ipam("10.0.0.0", 30, "10.255.0.0/16", vec!()).err().unwrap(), "invalid pool 10.0.0.0");
+    }
+
+    #[test]
+    fn capacity_excludes_reserved_addresses() {
+        let ipam = new_ipam("10.0.0.0/29", 30, "10.255.0.0/30", vec!("10.0.0.1")).unwrap();
+        assert!(ipam.check_capacity(1, 3).is_ok());
+        assert_eq!(ipam.check_capacity(2, 0).unwrap_err(), "not enough addresses in the link pool");
+        assert_eq!(ipam.check_capacity(0, 4).unwrap_err(), "not enough addresses in the loopback pool");
+    }
+}
@@ -0,0 +1,201 @@
+// Planning of how the links of an emunet are realised on the container servers.
+//
+// A link whose end devices are placed on the same server is realised as a veth pair.
+// A link whose end devices are placed on different servers is realised as a VXLAN tunnel
+// between the data ips of the two servers. The VNI of a VXLAN device must be unique on
+// each server, so the VNIs are allocated from a pool shared by all the emunets.
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::net::Edge;
+
+// VNI 0 is not used, and the VNI is a 24-bit number
+const MIN_VNI: u32 = 1;
+const MAX_VNI: u32 = (1 << 24) - 1;
+
+/// How a link is realised on the container servers.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LinkRealization {
+    /// Both end devices are on the same server, connected with a veth pair.
+    Veth,
+    /// The end devices are on different servers, connected with a VXLAN tunnel.
+    ///
+    /// `data_ips.0` is the data ip of the server of the device at `edge_uuid.0` of the link.
+    Vxlan { vni: u32, data_ips: (IpAddr, IpAddr) },
+}
+
+/// A VXLAN tunnel of an emunet.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Tunnel {
+    pub link: (Uuid, Uuid), // the end vertexes of the link
+    pub servers: (Uuid, Uuid), // the servers of the end vertexes
+}
+
+/// The VNIs taken by the emunets.
+#[derive(Deserialize, Serialize, Default, Debug)]
+pub struct VniPool {
+    used: BTreeMap<u32, Uuid>, // vni -> uuid of the emunet
+}
+
+impl VniPool {
+    /// Allocate `count` VNIs to `emunet`.
+    pub fn alloc(&mut self, emunet: Uuid, count: usize) -> Result<Vec<u32>, String> {
+        if count as u64 > (MAX_VNI - MIN_VNI + 1) as u64 - self.used.len() as u64 {
+            return Err("VNIs are exhausted".to_string());
+        }
+        let mut vnis = Vec::new();
+        let mut vni = MIN_VNI;
+        while vnis.len() < count {
+            if !self.used.contains_key(&vni) {
+                self.used.insert(vni, emunet);
+                vnis.push(vni);
+            }
+            vni += 1;
+        }
+        Ok(vnis)
+    }
+
+    pub fn release(&mut self, vnis: &Vec<u32>) {
+        for vni in vnis.iter() {
+            self.used.remove(vni);
+        }
+    }
+
+    /// Release all the VNIs of `emunet`.
+    pub fn release_emunet(&mut self, emunet: &Uuid) {
+        self.used.retain(|_, owner| {owner != emunet});
+    }
+}
+
+/// Count the links whose end devices are on different servers.
+///
+/// `server_of` maps the uuid of each end vertex to the uuid of its server.
+pub fn count_tunnels(edges: &Vec<Edge>, server_of: &HashMap<Uuid, Uuid>) -> usize {
+    edges.iter().filter(|e| {
+        let edge_uuid = e.edge_uuid();
+        server_of.get(&edge_uuid.0).unwrap() != server_of.get(&edge_uuid.1).unwrap()
+    }).count()
+}
+
+/// Plan the realisation of each link, the links across servers take the `vnis` in order.
+///
+/// `data_ips` maps the uuid of each server to its data ip. Return the planned tunnels.
+pub fn plan_links(edges: &mut Vec<Edge>, server_of: &HashMap<Uuid, Uuid>, data_ips: &HashMap<Uuid, IpAddr>, vnis: Vec<u32>)
+    -> BTreeMap<u32, Tunnel>
+{
+    let mut vnis = vnis.into_iter();
+    let mut tunnels = BTreeMap::new();
+    for e in edges.iter_mut() {
+        let link = e.edge_uuid().clone();
+        let servers = (*server_of.get(&link.0).unwrap(), *server_of.get(&link.1).unwrap());
+        if servers.0 == servers.1 {
+            e.set_realization(LinkRealization::Veth);
+            continue;
+        }
+
+        let vni = vnis.next().expect("not enough VNIs, this should never happen");
+        e.set_realization(LinkRealization::Vxlan {
+            vni,
+            data_ips: (*data_ips.get(&servers.0).unwrap(), *data_ips.get(&servers.1).unwrap()),
+        });
+        tunnels.insert(vni, Tunnel { link, servers });
+    }
+    tunnels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emunet::net::LinkAttrs;
+
+    fn uuid() -> Uuid {
+        indradb::util::generate_uuid_v1()
+    }
+
+    #[test]
+    fn alloc_fails_when_exhausted() {
+        let mut pool = VniPool::default();
+        let emunet = uuid();
+        assert_eq!(pool.alloc(emunet, 3).unwrap(), vec!(1, 2, 3));
+
+        let free = (MAX_VNI - MIN_VNI + 1) as usize - 3;
+        assert_eq!(pool.alloc(emunet, free + 1).unwrap_err(), "VNIs are exhausted");
+        // a failed allocation takes nothing
+        assert_eq!(pool.used.len(), 3);
+        assert_eq!(pool.alloc(emunet, 0).unwrap(), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn released_vnis_are_reused() {
+        let mut pool = VniPool::default();
+        let emunet = uuid();
+        assert_eq!(pool.alloc(emunet, 3).unwrap(), vec!(1, 2, 3));
+        pool.release(&vec!(2));
+        assert_eq!(pool.alloc(emunet, 2).unwrap(), vec!(2, 4));
+
+        // releasing a free VNI does nothing
+        pool.release(&vec!(2, 100));
+        assert_eq!(pool.alloc(emunet, 1).unwrap(), vec!(2));
+    }
+
+    #[test]
+    fn release_emunet_keeps_other_emunets() {
+        let mut pool = VniPool::default();
+        let (emunet0, emunet1) = (uuid(), uuid());
+        assert_eq!(pool.alloc(emunet0, 2).unwrap(), vec!(1, 2));
+        assert_eq!(pool.alloc(emunet1, 1).unwrap(), vec!(3));
+        assert_eq!(pool.alloc(emunet0, 1).unwrap(), vec!(4));
+
+        pool.release_emunet(&emunet0);
+        assert_eq!(pool.used.iter().map(|(vni, owner)| {(*vni, *owner)}).collect::<Vec<_>>(), vec!((3, emunet1)));
+        assert_eq!(pool.alloc(emunet1, 3).unwrap(), vec!(1, 2, 4));
+    }
+
+    // devices 0 and 1 are on server 0, device 2 is on server 1, with the links
+    // (0, 1), (1, 2) and (2, 0)
+    fn links() -> (Vec<Uuid>, Vec<Uuid>, HashMap<Uuid, Uuid>, Vec<Edge>) {
+        let devs = vec!(uuid(), uuid(), uuid());
+        let servers = vec!(uuid(), uuid());
+        let server_of: HashMap<Uuid, Uuid> = vec!((devs[0], servers[0]), (devs[1], servers[0]), (devs[2], servers[1])).into_iter().collect();
+        let edges = vec!((0, 1), (1, 2), (2, 0)).into_iter().map(|(v0, v1)| {
+            Edge::new((devs[v0], devs[v1]), format!("link{}{}", v0, v1), LinkAttrs::default(), None)
+        }).collect();
+        (devs, servers, server_of, edges)
+    }
+
+    #[test]
+    fn links_across_servers_are_counted() {
+        let (_, _, server_of, mut edges) = links();
+        assert_eq!(count_tunnels(&edges, &server_of), 2);
+        edges.truncate(1);
+        assert_eq!(count_tunnels(&edges, &server_of), 0);
+    }
+
+    #[test]
+    fn links_are_planned_by_placement() {
+        let (devs, servers, server_of, mut edges) = links();
+        let data_ips: HashMap<Uuid, IpAddr> = vec!(
+            (servers[0], "10.0.0.1".parse().unwrap()), 
+            (servers[1], "10.0.0.2".parse().unwrap()),
+        ).into_iter().collect();
+        let tunnels = plan_links(&mut edges, &server_of, &data_ips, vec!(7, 9));
+
+        // the data ips follow the direction of the link
+        assert_eq!(edges[0].realization(), Some(&LinkRealization::Veth));
+        assert_eq!(edges[1].realization(), Some(&LinkRealization::Vxlan {
+            vni: 7, 
+            data_ips: ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()),
+        }));
+        assert_eq!(edges[2].realization(), Some(&LinkRealization::Vxlan {
+            vni: 9, 
+            data_ips: ("10.0.0.2".parse().unwrap(), "10.0.0.1".parse().unwrap()),
+        }));
+        assert_eq!(tunnels.len(), 2);
+        assert_eq!(tunnels.get(&7).unwrap(), &Tunnel { link: (devs[1], devs[2]), servers: (servers[0], servers[1]) });
+        assert_eq!(tunnels.get(&9).unwrap(), &Tunnel { link: (devs[2], devs[0]), servers: (servers[1], servers[0]) });
+    }
+}
@@ -1,3 +1,5 @@
 pub mod server;
 pub mod user;
-pub mod net;
\ No newline at end of file
+pub mod net;
+pub mod ipam;
+pub mod link_plan;
\ No newline at end of file
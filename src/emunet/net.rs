@@ -1,20 +1,279 @@
-use std::collections::{HashMap, hash_map::ValuesMut};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, hash_map::ValuesMut};
+use std::net::Ipv4Addr;
 
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use super::server::{ContainerServer};
+use super::server::{ContainerServer, Resources};
+use super::ipam::{Ipam, LinkAddrs};
+use super::link_plan::{LinkRealization, Tunnel};
+use crate::algo::{PartitionItem, PartitionStrategy};
+
+/// The role of a device in the emulated network.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceRole {
+    Router,
+    Switch,
+    Host,
+}
 
-#[derive(Deserialize, Serialize)]
+impl Default for DeviceRole {
+    fn default() -> Self {
+        DeviceRole::Host
+    }
+}
+
+impl DeviceRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceRole::Router => "router",
+            DeviceRole::Switch => "switch",
+            DeviceRole::Host => "host",
+        }
+    }
+}
+
+/// An interface of a device, which is attached to a link.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Interface {
+    pub index: u32,
+    pub name: String, // the name of the interface inside the container
+    pub peer: (u64, u32), // the client side id and the interface index of the device at the other end
+}
+
+/// The name of the interface with `index` inside the container.
+pub fn interface_name(index: u32) -> String {
+    format!("eth{}", index)
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct VertexInfo {
     id: u64, // client side vertex id in the form of u64
     description: String, // a description string to hold the place
+    #[serde(default)]
+    cpu: u32, // the cpu required by the device in millicores, which also limits the container
+    #[serde(default)]
+    memory: u32, // the memory required by the device in MiB, which also limits the container
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    image: Option<String>, // the container image, the default image of the server is used if not specified
+    #[serde(default)]
+    role: DeviceRole,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    command: Vec<String>, // the startup command, the default command of the image is used if empty
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>, // the environment variables of the container
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    total_ports: Option<u32>, // the number of interfaces, not limited if not specified
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    loopback: Option<Ipv4Addr>, // the allocated loopback address, only reported to the client
+    #[serde(skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    interfaces: Vec<Interface>, // the interfaces attached to links, only reported to the client
 }
 
 impl VertexInfo {
     pub fn id(&self) -> u64 {
         return self.id;
     }
+
+    pub fn image(&self) -> Option<&str> {
+        self.image.as_ref().map(|s| {s.as_str()})
+    }
+
+    pub fn role(&self) -> DeviceRole {
+        self.role
+    }
+
+    pub fn command(&self) -> &Vec<String> {
+        &self.command
+    }
+
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+
+    pub fn total_ports(&self) -> Option<u32> {
+        self.total_ports
+    }
+
+    /// Add an interface, the interfaces are kept in the order of their indexes.
+    pub fn add_interface(&mut self, iface: Interface) {
+        let pos = self.interfaces.iter().position(|i| {i.index > iface.index}).unwrap_or(self.interfaces.len());
+        self.interfaces.insert(pos, iface);
+    }
+
+    /// Check that the device can be launched as a container.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(image) = self.image.as_ref() {
+            if image.is_empty() || image.contains(char::is_whitespace) {
+                return Err(format!("invalid image \"{}\"", image));
+            }
+        }
+        if self.command.iter().any(|arg| {arg.is_empty()}) {
+            return Err("empty argument in command".to_string());
+        }
+        for name in self.env.keys() {
+            if name.is_empty() || name.contains('=') {
+                return Err(format!("invalid environment variable name \"{}\"", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// The resources required to launch the device, a device always takes a single container.
+    pub fn resources(&self) -> Resources {
+        Resources {
+            containers: 1,
+            cpu: self.cpu,
+            memory: self.memory,
+        }
+    }
+}
+
+/// Check that no device has more links than its interfaces.
+/// 
+/// `links` holds the client side ids of the links, a self loop takes two interfaces of the device.
+pub fn check_ports<'a, I, J>(devs: I, links: J) -> Result<(), String> 
+where
+    I: Iterator<Item = &'a VertexInfo>,
+    J: Iterator<Item = (u64, u64)>,
+{
+    let mut link_count: HashMap<u64, u32> = HashMap::new();
+    for (id0, id1) in links {
+        *link_count.entry(id0).or_insert(0) += 1;
+        *link_count.entry(id1).or_insert(0) += 1;
+    }
+    for vi in devs {
+        let count = link_count.get(&vi.id).cloned().unwrap_or(0);
+        match vi.total_ports {
+            Some(total_ports) if count > total_ports => {
+                return Err(format!("device {} has {} links but only {} ports", vi.id, count, total_ports));
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+/// The interfaces of the devices that are taken by the links.
+pub struct PortAllocator {
+    devices: HashMap<u64, (Option<u32>, BTreeSet<u32>)>, // total ports and the taken interfaces of each device
+}
+
+impl PortAllocator {
+    pub fn new() -> Self {
+        Self {
+            devices: HashMap::new(),
+        }
+    }
+
+    pub fn add_device(&mut self, id: u64, total_ports: Option<u32>) {
+        self.devices.insert(id, (total_ports, BTreeSet::new()));
+    }
+
+    /// Take the interface `port` of device `id`.
+    pub fn take(&mut self, id: u64, port: u32) -> Result<(), String> {
+        let (total_ports, used) = self.devices.get_mut(&id).ok_or(format!("device {} does not exist", id))?;
+        if total_ports.map(|total_ports| {port >= total_ports}).unwrap_or(false) {
+            return Err(format!("device {} has no interface {}", id, port));
+        }
+        if !used.insert(port) {
+            return Err(format!("interface {} of device {} is taken by multiple links", port, id));
+        }
+        Ok(())
+    }
+
+    /// Take the free interface with the smallest index of device `id`.
+    pub fn alloc(&mut self, id: u64) -> Result<u32, String> {
+        let port = {
+            let (_, used) = self.devices.get(&id).ok_or(format!("device {} does not exist", id))?;
+            (0..).find(|port| {!used.contains(port)}).unwrap()
+        };
+        self.take(id, port).map_err(|_| {format!("device {} has no free interface", id)})?;
+        Ok(port)
+    }
+
+    /// Assign the interfaces to the `links`, the interfaces specified by the client are taken
+    /// first, then each unspecified one takes the free interface with the smallest index.
+    pub fn assign<'a, I: Iterator<Item = &'a mut EdgeInfo>>(&mut self, links: I) -> Result<(), String> {
+        let mut links: Vec<&'a mut EdgeInfo> = links.collect();
+        for ei in links.iter() {
+            if let Some(port) = ei.ports.0 {
+                self.take(ei.edge_id.0, port)?;
+            }
+            if let Some(port) = ei.ports.1 {
+                self.take(ei.edge_id.1, port)?;
+            }
+        }
+        for ei in links.iter_mut() {
+            if ei.ports.0.is_none() {
+                ei.ports.0 = Some(self.alloc(ei.edge_id.0)?);
+            }
+            if ei.ports.1.is_none() {
+                ei.ports.1 = Some(self.alloc(ei.edge_id.1)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PartitionItem for VertexInfo {
+    type Size = Resources;
+
+    fn size(&self) -> Resources {
+        self.resources()
+    }
+}
+
+/// The traffic characteristics of a link, applied to both directions of the link.
+/// 
+/// An unspecified attribute is left to the default of the container server.
+#[derive(Deserialize, Serialize, Clone, Default, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LinkAttrs {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_kbps: Option<u64>, // bandwidth in kbit/s
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delay_us: Option<u32>, // one-way latency in microseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jitter_us: Option<u32>, // variation of the latency in microseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loss_percent: Option<f64>, // packet loss rate in percent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtu: Option<u32>, // mtu of the interfaces in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_size: Option<u32>, // length of the transmit queue in packets
+}
+
+impl LinkAttrs {
+    /// Check that the attributes can be applied to a link.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.rate_kbps == Some(0) {
+            return Err("rate_kbps must be positive".to_string());
+        }
+        if self.jitter_us.is_some() && self.delay_us.is_none() {
+            return Err("jitter_us requires delay_us".to_string());
+        }
+        if let (Some(jitter), Some(delay)) = (self.jitter_us, self.delay_us) {
+            if jitter > delay {
+                return Err("jitter_us can not exceed delay_us".to_string());
+            }
+        }
+        if let Some(loss) = self.loss_percent {
+            if !(loss >= 0.0 && loss <= 100.0) {
+                return Err("loss_percent must be within [0, 100]".to_string());
+            }
+        }
+        if let Some(mtu) = self.mtu {
+            if mtu < 68 || mtu > 65535 {
+                return Err("mtu must be within [68, 65535]".to_string());
+            }
+        }
+        if self.queue_size == Some(0) {
+            return Err("queue_size must be positive".to_string());
+        }
+        Ok(())
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -23,11 +282,26 @@ impl VertexInfo {
 pub struct EdgeInfo {
     edge_id: (u64, u64), // client side edge id in the form of (u64, u64)
     description: String, // a description string to hold the place
+    #[serde(default)]
+    attrs: LinkAttrs, // the traffic characteristics of the link
+    #[serde(default)]
+    ports: (Option<u32>, Option<u32>), // the interface indexes on the two end devices, assigned automatically if not specified
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    addrs: Option<LinkAddrs>, // the allocated addresses, only reported to the client
 }
 
 impl EdgeInfo {
-    pub fn new(edge_id: (u64, u64), description: String) -> EdgeInfo {
-        EdgeInfo{edge_id, description}
+    pub fn new(edge_id: (u64, u64), description: String, attrs: LinkAttrs, ports: Option<(u32, u32)>, addrs: Option<LinkAddrs>) -> EdgeInfo {
+        let ports = (ports.map(|p| {p.0}), ports.map(|p| {p.1}));
+        EdgeInfo{edge_id, description, attrs, ports, addrs}
+    }
+
+    /// The interface indexes on the two end devices, `None` if they are not assigned yet.
+    pub fn ports(&self) -> Option<(u32, u32)> {
+        match self.ports {
+            (Some(port0), Some(port1)) => Some((port0, port1)),
+            _ => None,
+        }
     }
 
     pub fn edge_id(&self) -> (u64, u64) {
@@ -41,6 +315,18 @@ impl EdgeInfo {
     pub fn description(&self) -> String {
         self.description.clone()
     }
+
+    pub fn attrs(&self) -> &LinkAttrs {
+        &self.attrs
+    }
+}
+
+// The launch state of a device on its container server.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub enum DeviceState {
+    Pending, // the device has not been launched
+    Running, // the device and all of its links are successfully created
+    Failed(String), // the launch fails with the error message
 }
 
 #[derive(Deserialize, Serialize)]
@@ -48,26 +334,26 @@ pub struct Vertex {
     info: VertexInfo,
     uuid: uuid::Uuid,
     server_uuid: uuid::Uuid, // which server this vertex is launched on
-    edges: HashMap<uuid::Uuid, Edge>
+    state: DeviceState,
+    #[serde(default)]
+    loopback: Option<Ipv4Addr>,
 }
 
 impl Vertex {
     pub fn new(info: VertexInfo, uuid: uuid::Uuid, server_uuid: uuid::Uuid) -> Self {
         Self{
             info, uuid, server_uuid, 
-            edges: HashMap::new()
+            state: DeviceState::Pending,
+            loopback: None,
         }
     }
 
-    pub fn add_edge(&mut self, edge: Edge) -> Result<(), String> {
-        // valid the edge
-        if self.uuid() != edge.edge_uuid.0 || self.edges.contains_key(&edge.edge_uuid.1) {
-            return Err("invalid edge id".to_string());
-        }
-        if self.edges.insert(edge.edge_uuid.1.clone(), edge).is_some() {
-            panic!("fatal!".to_string());
-        }
-        Ok(())
+    pub fn set_state(&mut self, state: DeviceState) {
+        self.state = state;
+    }
+
+    pub fn set_loopback(&mut self, loopback: Ipv4Addr) {
+        self.loopback = Some(loopback);
     }
 }
 
@@ -80,28 +366,59 @@ impl Vertex {
         return self.uuid.clone()
     }
 
-    pub fn vertex_info(&self) -> VertexInfo {
-        VertexInfo {
-            id: self.info.id,
-            description: self.info.description.clone()
-        }
+    pub fn server_uuid(&self) -> uuid::Uuid {
+        return self.server_uuid.clone()
+    }
+
+    pub fn description(&self) -> String {
+        self.info.description.clone()
+    }
+
+    pub fn state(&self) -> &DeviceState {
+        &self.state
+    }
+
+    pub fn resources(&self) -> Resources {
+        self.info.resources()
+    }
+
+    pub fn loopback(&self) -> Option<Ipv4Addr> {
+        self.loopback
     }
 
-    pub fn edges<'a>(&'a self) -> impl Iterator<Item = &'a Edge> + 'a {
-        self.edges.values()
+    pub fn vertex_info(&self) -> VertexInfo {
+        let mut info = self.info.clone();
+        info.loopback = self.loopback;
+        info
     }
 }
 
 // This represents a directed Edge!!
+// Each undirected link is stored in the database as a single directed edge 
+// going from `edge_uuid.0` to `edge_uuid.1`, and this is the property of that edge.
 #[derive(Deserialize, Serialize)]
 pub struct Edge {
     edge_uuid: (uuid::Uuid, uuid::Uuid), // out-going vertex -> incoming vertex
     description: String,
+    #[serde(default)]
+    attrs: LinkAttrs,
+    #[serde(default)]
+    addrs: Option<LinkAddrs>,
+    #[serde(default)]
+    ports: Option<(u32, u32)>, // the interface indexes on the two end devices
+    #[serde(default)]
+    index: u32, // distinguishes the parallel links between the same pair of devices
+    #[serde(default)]
+    realization: Option<LinkRealization>, // how the link is realised on the servers
 }
 
 impl Edge {
-    pub fn new(edge_uuid: (uuid::Uuid, uuid::Uuid), description: String) -> Self {
-        Self{edge_uuid, description}
+    pub fn new(edge_uuid: (uuid::Uuid, uuid::Uuid), description: String, attrs: LinkAttrs, ports: Option<(u32, u32)>) -> Self {
+        Self{edge_uuid, description, attrs, addrs: None, ports, index: 0, realization: None}
+    }
+
+    pub fn set_addrs(&mut self, addrs: LinkAddrs) {
+        self.addrs = Some(addrs);
     }
 }
 
@@ -113,20 +430,77 @@ impl Edge {
     pub fn description(&self) -> String {
         self.description.clone()
     }
+
+    pub fn attrs(&self) -> &LinkAttrs {
+        &self.attrs
+    }
+
+    pub fn addrs(&self) -> Option<&LinkAddrs> {
+        self.addrs.as_ref()
+    }
+
+    pub fn ports(&self) -> Option<(u32, u32)> {
+        self.ports
+    }
+
+    pub fn set_realization(&mut self, realization: LinkRealization) {
+        self.realization = Some(realization);
+    }
+
+    /// How the link is realised, `None` for the links created before the planning.
+    pub fn realization(&self) -> Option<&LinkRealization> {
+        self.realization.as_ref()
+    }
+
+    /// The key of the directed edge in the database, in the form of 
+    /// (outbound vertex uuid, edge type, inbound vertex uuid).
+    /// 
+    /// The first link between a pair of devices has the type of the vertexes, 
+    /// and each parallel link has the index appended to the type.
+    pub fn edge_key(&self, vertex_type: &str) -> (uuid::Uuid, String, uuid::Uuid) {
+        let t = if self.index == 0 {
+            vertex_type.to_string()
+        }
+        else {
+            format!("{}-{}", vertex_type, self.index)
+        };
+        (self.edge_uuid.0, t, self.edge_uuid.1)
+    }
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+/// Number the parallel links between each pair of devices, skipping the indexes taken by
+/// the `existing` links.
+pub fn assign_link_indexes<'a, I: Iterator<Item = &'a Edge>>(existing: I, links: &mut Vec<Edge>) {
+    let pair = |edge_uuid: &(Uuid, Uuid)| {
+        if edge_uuid.0 < edge_uuid.1 { *edge_uuid } else { (edge_uuid.1, edge_uuid.0) }
+    };
+    let mut used: HashMap<(Uuid, Uuid), BTreeSet<u32>> = HashMap::new();
+    for e in existing {
+        used.entry(pair(&e.edge_uuid)).or_insert_with(BTreeSet::new).insert(e.index);
+    }
+    for e in links.iter_mut() {
+        let indexes = used.entry(pair(&e.edge_uuid)).or_insert_with(BTreeSet::new);
+        e.index = (0..).find(|index| {!indexes.contains(index)}).unwrap();
+        indexes.insert(e.index);
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub enum EmuNetError {
     PartitionFail(String),
     DatabaseFail(String),
+    LaunchFail(String),
+    LinkPlanFail(String),
+    RemoveFail(String),
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub enum EmuNetState {
     Uninit,
     Working,
     Normal,
     Error(EmuNetError),
+    ServerDown(Vec<Uuid>), // some servers of a normal emunet are down
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -138,6 +512,12 @@ pub struct EmuNet {
     state: EmuNetState,
     server_map: HashMap<Uuid, ContainerServer>,
     vertex_map: HashMap<u64, Uuid>,
+    #[serde(default)]
+    partition_strategy: PartitionStrategy, // how the devices are placed on the servers
+    #[serde(default)]
+    ipam: Option<Ipam>, // the address allocation, the emunets initialized without it have no addresses
+    #[serde(default)]
+    tunnels: BTreeMap<u32, Tunnel>, // the VXLAN tunnels of the links across servers, indexed by VNI
 }
 
 impl EmuNet {
@@ -150,6 +530,9 @@ impl EmuNet {
             state: EmuNetState::Uninit,
             server_map: HashMap::new(),
             vertex_map: HashMap::new(),
+            partition_strategy: PartitionStrategy::default(),
+            ipam: None,
+            tunnels: BTreeMap::new(),
         }
     }
 
@@ -163,20 +546,78 @@ impl EmuNet {
         }
     }
 
+    pub fn release_servers(&mut self) -> Vec<ContainerServer> {
+        self.server_map.drain().map(|(_, cs)| {cs}).collect()
+    }
+
+    pub fn servers<'a>(&'a self) -> impl Iterator<Item = &'a ContainerServer> + 'a {
+        self.server_map.values()
+    }
+
     pub fn servers_mut<'a>(&'a mut self) ->  ValuesMut<'a, Uuid, ContainerServer>{
         self.server_map.values_mut()
     }
 
+    pub fn set_partition_strategy(&mut self, strategy: PartitionStrategy) {
+        self.partition_strategy = strategy;
+    }
+
+    pub fn partition_strategy(&self) -> PartitionStrategy {
+        self.partition_strategy
+    }
+
+    pub fn set_ipam(&mut self, ipam: Ipam) {
+        self.ipam = Some(ipam);
+    }
+
+    pub fn ipam_mut(&mut self) -> Option<&mut Ipam> {
+        self.ipam.as_mut()
+    }
+
+    /// The data ips of the servers of the emunet.
+    pub fn server_data_ips(&self) -> HashMap<Uuid, std::net::IpAddr> {
+        self.server_map.values().map(|cs| {(cs.id(), cs.ips().0)}).collect()
+    }
+
+    pub fn add_tunnels(&mut self, tunnels: BTreeMap<u32, Tunnel>) {
+        self.tunnels.extend(tunnels.into_iter());
+    }
+
+    pub fn remove_tunnel(&mut self, vni: u32) -> Option<Tunnel> {
+        self.tunnels.remove(&vni)
+    }
+
+    /// The IPv4 data and management addresses of the servers of the emunet.
+    pub fn server_ipv4s(&self) -> Vec<Ipv4Addr> {
+        self.server_map.values().flat_map(|cs| {
+            let (data_ip, man_ip) = cs.ips();
+            vec!(data_ip, man_ip).into_iter()
+        }).filter_map(|ip| {
+            match ip {
+                std::net::IpAddr::V4(ip) => Some(ip),
+                _ => None,
+            }
+        }).collect()
+    }
+
     pub fn add_vertex(&mut self, vertex_client_id: u64, vertex_uuid: Uuid) {
         self.vertex_map.insert(vertex_client_id, vertex_uuid);
     }
 
+    pub fn remove_vertex(&mut self, vertex_client_id: u64) -> Option<Uuid> {
+        self.vertex_map.remove(&vertex_client_id)
+    }
+
     pub fn reserve_capacity(&mut self, reserved_capacity: u32) {
         if reserved_capacity > self.capacity {
             panic!("this should never happen");
         }
         self.capacity -= reserved_capacity;
     }
+
+    pub fn release_capacity(&mut self, released_capacity: u32) {
+        self.capacity += released_capacity;
+    }
 }
 
 impl EmuNet {
@@ -206,6 +647,20 @@ impl EmuNet {
         }
     }
 
+    pub fn is_normal(&self) -> bool {
+        match self.state {
+            EmuNetState::Normal => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_working(&self) -> bool {
+        match self.state {
+            EmuNetState::Working => true,
+            _ => false,
+        }
+    }
+
     pub fn working(&mut self) {
         self.state = EmuNetState::Working;
     }
@@ -217,4 +672,145 @@ impl EmuNet {
     pub fn normal(&mut self) {
         self.state = EmuNetState::Normal;
     }
+
+    /// Flag the emunet if any of its servers is in `down_servers`, and clear the flag
+    /// when all of its servers are back.
+    /// 
+    /// Only a normal or a flagged emunet is updated. Return whether the state is changed.
+    pub fn update_server_health(&mut self, down_servers: &HashSet<Uuid>) -> bool {
+        match self.state {
+            EmuNetState::Normal | EmuNetState::ServerDown(_) => {},
+            _ => return false,
+        };
+
+        let mut down: Vec<Uuid> = self.server_map.keys().filter(|id| {down_servers.contains(id)}).cloned().collect();
+        down.sort();
+        let new_state = if down.is_empty() {
+            EmuNetState::Normal
+        }
+        else {
+            EmuNetState::ServerDown(down)
+        };
+        if new_state == self.state {
+            false
+        }
+        else {
+            self.state = new_state;
+            true
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(edge_uuid: (Uuid, Uuid)) -> Edge {
+        Edge::new(edge_uuid, "link".to_string(), LinkAttrs::default(), None)
+    }
+
+    #[test]
+    fn parallel_links_have_distinct_keys() {
+        let (a, b, c) = (indradb::util::generate_uuid_v1(), indradb::util::generate_uuid_v1(), indradb::util::generate_uuid_v1());
+        let mut links = vec!(link((a, b)), link((b, a)), link((a, c)), link((a, b)));
+        assign_link_indexes(std::iter::empty(), &mut links);
+        let keys: Vec<(Uuid, String, Uuid)> = links.iter().map(|e| {e.edge_key("user-net")}).collect();
+        assert_eq!(keys, vec!(
+            (a, "user-net".to_string(), b),
+            (b, "user-net-1".to_string(), a),
+            (a, "user-net".to_string(), c),
+            (a, "user-net-2".to_string(), b),
+        ));
+    }
+
+    #[test]
+    fn new_links_skip_existing_indexes() {
+        let (a, b) = (indradb::util::generate_uuid_v1(), indradb::util::generate_uuid_v1());
+        let mut existing = vec!(link((a, b)), link((a, b)), link((b, a)));
+        assign_link_indexes(std::iter::empty(), &mut existing);
+        // the link with index 1 is removed
+        existing.remove(1);
+
+        let mut links = vec!(link((b, a)), link((a, b)));
+        assign_link_indexes(existing.iter(), &mut links);
+        assert_eq!(links.iter().map(|e| {e.index}).collect::<Vec<_>>(), vec!(1, 3));
+        assert_eq!(links[0].edge_key("user-net"), (b, "user-net-1".to_string(), a));
+    }
+
+    fn attrs(value: serde_json::Value) -> LinkAttrs {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn link_attrs_are_validated() {
+        assert_eq!(LinkAttrs::default().validate(), Ok(()));
+        let valid = attrs(serde_json::json!({
+            "rate_kbps": 1000, "delay_us": 100, "jitter_us": 100, "loss_percent": 100.0, "mtu": 65535, "queue_size": 1
+        }));
+        assert_eq!(valid.validate(), Ok(()));
+        assert_eq!(attrs(serde_json::json!({"mtu": 68, "loss_percent": 0.0})).validate(), Ok(()));
+
+        let invalid = vec!(
+            (serde_json::json!({"rate_kbps": 0}), "rate_kbps must be positive"),
+            (serde_json::json!({"jitter_us": 10}), "jitter_us requires delay_us"),
+            (serde_json::json!({"delay_us": 10, "jitter_us": 11}), "jitter_us can not exceed delay_us"),
+            (serde_json::json!({"loss_percent": -0.1}), "loss_percent must be within [0, 100]"),
+            (serde_json::json!({"loss_percent": 100.5}), "loss_percent must be within [0, 100]"),
+            (serde_json::json!({"mtu": 67}), "mtu must be within [68, 65535]"),
+            (serde_json::json!({"mtu": 65536}), "mtu must be within [68, 65535]"),
+            (serde_json::json!({"queue_size": 0}), "queue_size must be positive"),
+        );
+        for (value, err) in invalid.into_iter() {
+            assert_eq!(attrs(value).validate().unwrap_err(), err);
+        }
+        // a NaN loss rate is refused as well
+        let nan = LinkAttrs { loss_percent: Some(f64::NAN), ..LinkAttrs::default() };
+        assert_eq!(nan.validate().unwrap_err(), "loss_percent must be within [0, 100]");
+        assert!(serde_json::from_value::<LinkAttrs>(serde_json::json!({"latency": 10})).is_err());
+    }
+
+    fn dev(value: serde_json::Value) -> VertexInfo {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn ports_are_checked_against_links() {
+        let devs = vec!(
+            dev(serde_json::json!({"id": 0, "description": "", "total_ports": 2})),
+            dev(serde_json::json!({"id": 1, "description": ""})),
+        );
+        assert_eq!(check_ports(devs.iter(), vec!((0, 1), (1, 0)).into_iter()), Ok(()));
+        assert_eq!(
+            check_ports(devs.iter(), vec!((0, 1), (1, 0), (0, 1)).into_iter()).unwrap_err(), 
+            "device 0 has 3 links but only 2 ports"
+        );
+        // a self loop takes two interfaces
+        assert_eq!(check_ports(devs.iter(), vec!((0, 0)).into_iter()), Ok(()));
+        assert!(check_ports(devs.iter(), vec!((0, 0), (0, 1)).into_iter()).is_err());
+        // a device without total_ports takes any number of links
+        assert_eq!(check_ports(devs.iter(), (0..10).map(|_| {(1, 1)})), Ok(()));
+    }
+
+    #[test]
+    fn vertex_info_is_validated() {
+        let valid = dev(serde_json::json!({
+            "id": 0, "description": "", "image": "alpine:3", "command": ["sleep", "infinity"], "env": {"PATH": "/bin"}
+        }));
+        assert_eq!(valid.validate(), Ok(()));
+        assert_eq!(dev(serde_json::json!({"id": 0, "description": ""})).validate(), Ok(()));
+
+        let invalid = vec!(
+            (serde_json::json!({"image": ""}), "invalid image \"\""),
+            (serde_json::json!({"image": "alpine 3"}), "invalid image \"alpine 3\""),
+            (serde_json::json!({"command": ["sleep", ""]}), "empty argument in command"),
+            (serde_json::json!({"env": {"": "x"}}), "invalid environment variable name \"\""),
+            (serde_json::json!({"env": {"A=B": "x"}}), "invalid environment variable name \"A=B\""),
+        );
+        for (mut value, err) in invalid.into_iter() {
+            value["id"] = serde_json::json!(0);
+            value["description"] = serde_json::json!("");
+            assert_eq!(dev(value).validate().unwrap_err(), err);
+        }
+    }
 }
@@ -1,11 +1,42 @@
 use std::net::{IpAddr, SocketAddr};
 use std::cmp::Ord;
+use std::fmt;
 
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
 
 use crate::algo::PartitionBin;
 
+/// Errors of registering a server.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerRegistrationError {
+    InvalidIp(String), // the string can not be parsed as an IP address
+    UnreachablePort(u16), // the connection port can not be reached
+    ZeroCapacity, // the server can not launch any container
+    DuplicateConnAddr(SocketAddr), // the connection address is used by another server
+    DuplicateDataIp(IpAddr), // the data IP is used by another server
+    DuplicateManIp(IpAddr), // the management IP is used by another server
+    AddressChanged(SocketAddr), // the data IP or management IP of a stored server is changed
+    CapacityInUse(SocketAddr), // the capacity of a stored server is lowered below its allocated part
+}
+
+impl fmt::Display for ServerRegistrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerRegistrationError::InvalidIp(ip) => write!(f, "invalid IP address {}", ip),
+            ServerRegistrationError::UnreachablePort(port) => write!(f, "connection port {} can not be reached", port),
+            ServerRegistrationError::ZeroCapacity => write!(f, "the maximum capacity of the server is zero"),
+            ServerRegistrationError::DuplicateConnAddr(addr) => write!(f, "connection address {} is used by another server", addr),
+            ServerRegistrationError::DuplicateDataIp(ip) => write!(f, "data IP {} is used by another server", ip),
+            ServerRegistrationError::DuplicateManIp(ip) => write!(f, "management IP {} is used by another server", ip),
+            ServerRegistrationError::AddressChanged(addr) => write!(f, "the data IP or management IP of server {} can not be changed", addr),
+            ServerRegistrationError::CapacityInUse(addr) => write!(f, "the capacity of server {} is less than the part allocated to emunets", addr),
+        }
+    }
+}
+
+impl std::error::Error for ServerRegistrationError {}
+
 // The IP addresses that are used to talk to the server.
 #[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
 struct ServerAddress {
@@ -15,33 +46,243 @@ struct ServerAddress {
     man_ip: IpAddr,
 }
 
+// port 0 can not be connected to
+fn check_port(conn_port: u16) -> Result<(), ServerRegistrationError> {
+    if conn_port == 0 {
+        return Err(ServerRegistrationError::UnreachablePort(conn_port));
+    }
+    Ok(())
+}
+
 impl ServerAddress {
-    fn new(conn_ip: &str, conn_port: u16, data_ip: &str, man_ip: &str) -> Option<Self> {
-        conn_ip.parse::<IpAddr>().ok().and_then(move |conn_ip| {
-            data_ip.parse::<IpAddr>().ok().and_then(move |data_ip| {
-                man_ip.parse::<IpAddr>().ok().map(move |man_ip|{
-                    Self {
-                        conn_ip,
-                        conn_port,
-                        data_ip,
-                        man_ip
-                    }
-                })
-            })
+    fn new(conn_ip: &str, conn_port: u16, data_ip: &str, man_ip: &str) -> Result<Self, ServerRegistrationError> {
+        let parse = |ip: &str| {
+            ip.parse::<IpAddr>().map_err(|_| {ServerRegistrationError::InvalidIp(ip.to_string())})
+        };
+        check_port(conn_port)?;
+        Ok(Self {
+            conn_ip: parse(conn_ip)?,
+            conn_port,
+            data_ip: parse(data_ip)?,
+            man_ip: parse(man_ip)?,
         })
     }
 }
 
+/// An amount of resources, used as both the requirement of devices
+/// and the capacity of servers.
+/// 
+/// `containers`: the number of containers,
+/// `cpu`: the cpu in millicores,
+/// `memory`: the memory in MiB
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub struct Resources {
+    pub containers: u32,
+    pub cpu: u32,
+    pub memory: u32,
+}
+
+impl Resources {
+    /// Whether `demand` can be satisfied by `self` in every dimension.
+    pub fn fits(&self, demand: &Resources) -> bool {
+        self.containers >= demand.containers && self.cpu >= demand.cpu && self.memory >= demand.memory
+    }
+
+    /// Add up `other` to `self` in every dimension, saturating at the maximum value.
+    pub fn add(&mut self, other: &Resources) {
+        self.containers = self.containers.saturating_add(other.containers);
+        self.cpu = self.cpu.saturating_add(other.cpu);
+        self.memory = self.memory.saturating_add(other.memory);
+    }
+}
+
+// cpu and memory are not limited for the servers stored without them
+fn unlimited() -> u32 {
+    u32::MAX
+}
+
 /// Core information of a server.
 /// 
 /// `id`: the id of the server, 
 /// `server_addr`: server addresses,
-/// `max_capacity`: the maximum number of containers that can be launched in the server
+/// `max_capacity`: the maximum number of containers that can be launched in the server,
+/// `max_cpu`: the cpu in millicores that can be used by the containers,
+/// `max_memory`: the memory in MiB that can be used by the containers,
+/// `allocated`: the number of containers that are allocated to the emunets,
+/// `allocated_cpu`, `allocated_memory`: the cpu and memory that are allocated to the emunets,
+/// `draining`: whether the server is excluded from new allocations,
+/// `health`: the result of the latest health check,
+/// `last_seen`: the last time that the server passes the health check, in seconds since the UNIX epoch
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ServerInfo {
     id: Uuid,
     server_addr: ServerAddress,
     max_capacity: u32,
+    #[serde(default = "unlimited")]
+    max_cpu: u32,
+    #[serde(default = "unlimited")]
+    max_memory: u32,
+    #[serde(default)]
+    allocated: u32,
+    #[serde(default)]
+    allocated_cpu: u32,
+    #[serde(default)]
+    allocated_memory: u32,
+    #[serde(default)]
+    draining: bool,
+    #[serde(default)]
+    health: ServerHealth,
+    #[serde(default)]
+    last_seen: Option<u64>,
+}
+
+/// The health of a server reported by the health checker.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServerHealth {
+    Unknown, // the server has not been checked yet
+    Up,
+    Down,
+}
+
+impl Default for ServerHealth {
+    fn default() -> Self {
+        ServerHealth::Unknown
+    }
+}
+
+impl ServerInfo {
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn conn_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.server_addr.conn_ip, self.server_addr.conn_port)
+    }
+
+    pub fn health(&self) -> ServerHealth {
+        self.health
+    }
+
+    /// The number of containers that are allocated to the emunets.
+    pub fn allocated(&self) -> u32 {
+        self.allocated
+    }
+
+    /// The number of containers that can still be allocated.
+    /// 
+    /// A draining server or a server that is down has no remaining capacity.
+    pub fn remaining_capacity(&self) -> u32 {
+        if self.draining || self.health == ServerHealth::Down {
+            0
+        }
+        else {
+            self.max_capacity.saturating_sub(self.allocated)
+        }
+    }
+
+    // Allocate `share` containers together with the cpu and memory that come with them.
+    fn allocate(&mut self, share: u32) -> ContainerServer {
+        let cpu = resource_share(self.max_cpu, self.allocated_cpu, share, self.max_capacity);
+        let memory = resource_share(self.max_memory, self.allocated_memory, share, self.max_capacity);
+        self.allocated += share;
+        if cpu != u32::MAX {
+            self.allocated_cpu += cpu;
+        }
+        if memory != u32::MAX {
+            self.allocated_memory += memory;
+        }
+        ContainerServer::new(self.clone(), share, cpu, memory)
+    }
+}
+
+// The part of `max` that comes with `share` out of `capacity` containers, bounded by 
+// the part that is not `allocated` yet. An unlimited resource is not divided.
+fn resource_share(max: u32, allocated: u32, share: u32, capacity: u32) -> u32 {
+    if max == u32::MAX {
+        return u32::MAX;
+    }
+    let part = (max as u64 * share as u64 / capacity as u64) as u32;
+    std::cmp::min(part, max.saturating_sub(allocated))
+}
+
+/// The policies for allocating the capacity of the servers to an emunet.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AllocationPolicy {
+    /// Take the capacity from the servers in the order they are added to the pool.
+    FirstFit,
+    /// Take the capacity from the server whose remaining capacity fits most tightly,
+    /// falling back to the servers with the most remaining capacity if no single server fits.
+    BestFit,
+    /// Spread the capacity evenly over all the servers.
+    Spread,
+}
+
+impl Default for AllocationPolicy {
+    fn default() -> Self {
+        AllocationPolicy::BestFit
+    }
+}
+
+// The following functions compute the number of containers taken from each server,
+// given the remaining capacity of each server. The total remaining capacity must be
+// no less than `quantity`.
+
+fn first_fit(remaining: &Vec<u32>, quantity: u32) -> Vec<u32> {
+    let mut need = quantity;
+    remaining.iter().map(|r| {
+        let share = std::cmp::min(*r, need);
+        need -= share;
+        share
+    }).collect()
+}
+
+fn best_fit(remaining: &Vec<u32>, quantity: u32) -> Vec<u32> {
+    let mut shares = vec![0; remaining.len()];
+    let mut need = quantity;
+    while need > 0 {
+        let unused: Vec<usize> = (0..remaining.len()).filter(|i| {shares[*i] == 0 && remaining[*i] > 0}).collect();
+        // the tightest single server that fits the rest
+        let fit = unused.iter().cloned().filter(|i| {remaining[*i] >= need}).min_by_key(|i| {remaining[*i]});
+        match fit {
+            Some(i) => {
+                shares[i] = need;
+                need = 0;
+            },
+            None => {
+                // otherwise use up the largest server
+                let i = unused.into_iter().max_by_key(|i| {remaining[*i]}).unwrap();
+                shares[i] = remaining[i];
+                need -= remaining[i];
+            }
+        }
+    }
+    shares
+}
+
+fn spread(remaining: &Vec<u32>, quantity: u32) -> Vec<u32> {
+    let mut shares = vec![0; remaining.len()];
+    let mut need = quantity;
+    while need > 0 {
+        for (share, r) in shares.iter_mut().zip(remaining.iter()) {
+            if need > 0 && *share < *r {
+                *share += 1;
+                need -= 1;
+            }
+        }
+    }
+    shares
+}
+
+/// The changes made by reconciling the stored server list with the configured servers.
+/// 
+/// The servers are identified by their connection addresses.
+#[derive(Serialize, Default, Debug)]
+pub struct ReconcileReport {
+    pub added: Vec<SocketAddr>, // the configured servers that are not stored
+    pub updated: Vec<SocketAddr>, // the stored servers whose capacities are changed
+    pub removed: Vec<SocketAddr>, // the stored servers that are not configured
+    pub in_use: Vec<SocketAddr>, // the stored servers that are not configured but kept, since they are allocated to emunets
 }
 
 /// A list of `ServerInfo` that can be stored in the database as JSON value.
@@ -51,10 +292,25 @@ pub struct ServerInfoList {
 }
 
 impl ServerInfoList {
-    fn server_addr_exist(&self, server_addr: &ServerAddress) -> bool {
-        let mut sorted: Vec<&ServerAddress> = self.servers.iter().map(|e|{&e.server_addr}).collect();
-        sorted.sort();
-        sorted.binary_search(&server_addr).is_ok()
+    // Check whether a server can be added to the list.
+    fn check_server(&self, server_info: &ServerInfo) -> Result<(), ServerRegistrationError> {
+        let addr = &server_info.server_addr;
+        if server_info.max_capacity == 0 {
+            return Err(ServerRegistrationError::ZeroCapacity);
+        }
+        for si in self.servers.iter() {
+            let other = &si.server_addr;
+            if other.conn_ip == addr.conn_ip && other.conn_port == addr.conn_port {
+                return Err(ServerRegistrationError::DuplicateConnAddr(SocketAddr::new(addr.conn_ip, addr.conn_port)));
+            }
+            if other.data_ip == addr.data_ip {
+                return Err(ServerRegistrationError::DuplicateDataIp(addr.data_ip));
+            }
+            if other.man_ip == addr.man_ip {
+                return Err(ServerRegistrationError::DuplicateManIp(addr.man_ip));
+            }
+        }
+        Ok(())
     }
 
     pub fn new() -> Self {
@@ -64,100 +320,302 @@ impl ServerInfoList {
     }
 
     /// Add a new server to the list.
+    /// 
+    /// Return the id of the new server.
     pub fn add_server_info(&mut self, conn_ip: &str, conn_port: u16, data_ip: &str, man_ip: &str, 
-                           max_capacity: u32) -> Result<(), String>
+                           max_capacity: u32, max_cpu: u32, max_memory: u32) -> Result<Uuid, ServerRegistrationError>
     {
-        let address = ServerAddress::new(conn_ip, conn_port, data_ip, man_ip).unwrap();
-        // validate the address
-        if self.server_addr_exist(&address) {
-            return Err(format!("Address {:?} is already stored in the list.", &address));
-        }
-        
-        self.servers.push(ServerInfo {
+        let server_info = ServerInfo {
             id: indradb::util::generate_uuid_v1(),
-            server_addr: address,
+            server_addr: ServerAddress::new(conn_ip, conn_port, data_ip, man_ip)?,
             max_capacity,
-        });
+            max_cpu,
+            max_memory,
+            allocated: 0,
+            allocated_cpu: 0,
+            allocated_memory: 0,
+            draining: false,
+            health: ServerHealth::Unknown,
+            last_seen: None,
+        };
+        // validate the server
+        self.check_server(&server_info)?;
+        
+        let id = server_info.id;
+        self.servers.push(server_info);
+        Ok(id)
+    }
 
+    /// Reconcile the list with the `configured` servers.
+    /// 
+    /// The configured servers that are not in the list are added, and the capacities of the 
+    /// servers in the list are updated to the configured ones. The servers that are not configured 
+    /// are removed, unless they are allocated to emunets. A configured server can not change the 
+    /// data IP or management IP of a stored server with the same connection address, nor lower its 
+    /// capacity below the allocated part. The list is unchanged on error.
+    pub fn reconcile(&mut self, configured: Vec<ServerInfo>) -> Result<ReconcileReport, ServerRegistrationError> {
+        let mut report = ReconcileReport::default();
+        let mut res = Self::new();
+
+        // update or remove the stored servers
+        for mut si in self.servers.iter().cloned() {
+            match configured.iter().find(|cs| {cs.conn_addr() == si.conn_addr()}) {
+                Some(cs) => {
+                    if (si.server_addr.data_ip, si.server_addr.man_ip) != (cs.server_addr.data_ip, cs.server_addr.man_ip) {
+                        return Err(ServerRegistrationError::AddressChanged(si.conn_addr()));
+                    }
+                    if cs.max_capacity < si.allocated || cs.max_cpu < si.allocated_cpu || cs.max_memory < si.allocated_memory {
+                        return Err(ServerRegistrationError::CapacityInUse(si.conn_addr()));
+                    }
+                    if (si.max_capacity, si.max_cpu, si.max_memory) != (cs.max_capacity, cs.max_cpu, cs.max_memory) {
+                        si.max_capacity = cs.max_capacity;
+                        si.max_cpu = cs.max_cpu;
+                        si.max_memory = cs.max_memory;
+                        report.updated.push(si.conn_addr());
+                    }
+                    res.servers.push(si);
+                },
+                None => {
+                    if si.allocated > 0 {
+                        report.in_use.push(si.conn_addr());
+                        res.servers.push(si);
+                    }
+                    else {
+                        report.removed.push(si.conn_addr());
+                    }
+                }
+            }
+        }
+
+        // add the new servers
+        for cs in configured.into_iter() {
+            if !res.servers.iter().any(|si| {si.conn_addr() == cs.conn_addr()}) {
+                res.check_server(&cs)?;
+                report.added.push(cs.conn_addr());
+                res.servers.push(cs);
+            }
+        }
+
+        *self = res;
+        Ok(report)
+    }
+
+    /// Set whether the server with `id` is excluded from new allocations.
+    /// 
+    /// The emunets that have been allocated on the server keep using it.
+    pub fn drain_server(&mut self, id: Uuid, draining: bool) -> Result<(), String> {
+        let si = self.servers.iter_mut().find(|si| {si.id == id}).ok_or(format!("server {} does not exist", id))?;
+        si.draining = draining;
         Ok(())
     }
 
-    /// Build `Self` from `Iterator`.
-    pub fn from_iterator<I: std::iter::Iterator<Item = ServerInfo>>(i: I) -> Result<Self, String> {
+    /// Record the result of a health check on the server with `id`.
+    /// 
+    /// `now` is the time of the check in seconds since the UNIX epoch.
+    pub fn set_health(&mut self, id: Uuid, up: bool, now: u64) -> Result<(), String> {
+        let si = self.servers.iter_mut().find(|si| {si.id == id}).ok_or(format!("server {} does not exist", id))?;
+        if up {
+            si.health = ServerHealth::Up;
+            si.last_seen = Some(now);
+        }
+        else {
+            si.health = ServerHealth::Down;
+        }
+        Ok(())
+    }
+
+    /// Remove the server with `id` from the list.
+    /// 
+    /// The caller has checked that no emunet uses the server while `allocated` containers of 
+    /// the server were allocated. The removal is refused if the allocation has changed since then.
+    pub fn remove_server(&mut self, id: Uuid, allocated: u32) -> Result<ServerInfo, String> {
+        let idx = self.servers.iter().position(|si| {si.id == id}).ok_or(format!("server {} does not exist", id))?;
+        if self.servers[idx].allocated != allocated {
+            return Err(format!("the allocation of server {} has changed, try again later", id));
+        }
+        Ok(self.servers.remove(idx))
+    }
+
+    /// Build `Self` from `Iterator`, each server is validated against the servers before it.
+    pub fn from_iterator<I: std::iter::Iterator<Item = ServerInfo>>(i: I) -> Result<Self, ServerRegistrationError> {
         let mut res = Self::new();
-        for cs in i {
-            if res.server_addr_exist(&cs.server_addr) {
-                return Err(format!("ServerAddr {:?} exists in the pool", &cs.server_addr));
-            }
-            res.servers.push(cs);
-        };
+        for si in i {
+            check_port(si.server_addr.conn_port)?;
+            res.check_server(&si)?;
+            res.servers.push(si);
+        }
         Ok(res)
     }
 
+    /// Build `Self` from the list stored in the database.
+    /// 
+    /// The servers are not validated, since a stored list may have been built before the 
+    /// validation is introduced. The new servers are validated when they are added.
+    pub fn from_stored<I: std::iter::Iterator<Item = ServerInfo>>(i: I) -> Self {
+        Self {
+            servers: i.collect()
+        }
+    }
+
+    /// Return the capacity allocated to an emunet back to the list.
+    pub fn release_servers(&mut self, servers: Vec<ContainerServer>) -> Result<(), String> {
+        // the servers that are not in the list are added back to the list
+        for cs in servers.iter() {
+            if !self.servers.iter().any(|si| {si.id == cs.server_info.id}) {
+                self.check_server(&cs.server_info).map_err(|e| {format!("{}", e)})?;
+            }
+        }
+        for cs in servers.into_iter() {
+            let amount = cs.allocated_capacity();
+            match self.servers.iter_mut().find(|si| {si.id == cs.server_info.id}) {
+                Some(si) => {
+                    si.allocated = si.allocated.saturating_sub(amount);
+                    // the unlimited shares are not counted
+                    if cs.allocated_cpu != u32::MAX {
+                        si.allocated_cpu = si.allocated_cpu.saturating_sub(cs.allocated_cpu);
+                    }
+                    if cs.allocated_memory != u32::MAX {
+                        si.allocated_memory = si.allocated_memory.saturating_sub(cs.allocated_memory);
+                    }
+                },
+                None => {
+                    let mut si = cs.server_info;
+                    si.allocated = 0;
+                    si.allocated_cpu = 0;
+                    si.allocated_memory = 0;
+                    self.servers.push(si);
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Convert `Self` into a `Vec`.
     pub fn into_vec(self) -> Vec<ServerInfo> {
         self.servers
     }
 
-    /// Use a simple greedy algorithm to allocate servers
-    pub fn allocate_servers(&mut self, quantity: u32) -> Result<Vec<ContainerServer>, u32> {
-        let mut target = 0;
+    /// Allocate `quantity` containers from the servers with the given policy.
+    /// 
+    /// A server may be shared by multiple emunets, each receiving part of its capacity,
+    /// and the cpu and memory in proportion to that part.
+    /// Return the total remaining capacity of the list if it is less than `quantity`.
+    pub fn allocate_servers(&mut self, quantity: u32, policy: AllocationPolicy) -> Result<Vec<ContainerServer>, u32> {
+        let remaining: Vec<u32> = self.servers.iter().map(|si| {si.remaining_capacity()}).collect();
+        let total = remaining.iter().fold(0, |total: u32, r| {total.saturating_add(*r)});
+        if total < quantity {
+            return Err(total);
+        }
 
-        let mut enumerate: Vec<(usize, u32)> = self.servers.iter().map(|e|{e.max_capacity}).enumerate().collect();
-        enumerate.sort_by(|a, b|{(&b.1).cmp(&a.1)});
-        
-        let mut index = 0;
-        while target < quantity && index < enumerate.len() {
-            target += enumerate[index].1;            
-            index += 1;
+        let shares = match policy {
+            AllocationPolicy::FirstFit => first_fit(&remaining, quantity),
+            AllocationPolicy::BestFit => best_fit(&remaining, quantity),
+            AllocationPolicy::Spread => spread(&remaining, quantity),
         };
-        
-        if target >= quantity {
-            Ok(
-                enumerate.iter().take(index).map(|e|{
-                    let server_info = self.servers.remove(e.0);
-                    let curr_capacity = server_info.max_capacity;
-                    ContainerServer {
-                        server_info,
-                        curr_capacity,
-                    }
-                }).collect()
-            )
-        }
-        else {
-            Err(target)
-        }
+        // the shares are indexed in the same order as the servers, and the servers are 
+        // updated in place, so no index is invalidated during the allocation
+        debug_assert!(shares.iter().zip(remaining.iter()).all(|(share, r)| {share <= r}));
+        debug_assert_eq!(shares.iter().sum::<u32>(), quantity);
+        Ok(
+            self.servers.iter_mut().zip(shares.into_iter()).filter(|(_, share)| {*share > 0}).map(|(si, share)| {
+                si.allocate(share)
+            }).collect()
+        )
     }
 }
 
+/// The part of a server that is allocated to an emunet.
+/// 
+/// `allocated` containers of the server are allocated to the emunet, together with 
+/// `allocated_cpu` and `allocated_memory`. The servers stored without the cpu and memory 
+/// may use all of them.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ContainerServer {
     server_info: ServerInfo,
     curr_capacity: u32,
+    #[serde(default = "unlimited")]
+    curr_cpu: u32,
+    #[serde(default = "unlimited")]
+    curr_memory: u32,
+    #[serde(default)]
+    allocated: u32,
+    #[serde(default = "unlimited")]
+    allocated_cpu: u32,
+    #[serde(default = "unlimited")]
+    allocated_memory: u32,
 }
 
 impl ContainerServer {
+    fn new(server_info: ServerInfo, allocated: u32, allocated_cpu: u32, allocated_memory: u32) -> Self {
+        Self {
+            curr_capacity: allocated,
+            curr_cpu: allocated_cpu,
+            curr_memory: allocated_memory,
+            allocated,
+            allocated_cpu,
+            allocated_memory,
+            server_info,
+        }
+    }
+
+    // The number of containers allocated to the emunet.
+    fn allocated_capacity(&self) -> u32 {
+        // the servers stored without `allocated` were allocated as a whole
+        if self.allocated == 0 {
+            self.server_info.max_capacity
+        }
+        else {
+            self.allocated
+        }
+    }
+
     pub fn id(&self) -> Uuid {
         return self.server_info.id;
     }
 
 
+    pub fn curr_capacity(&self) -> u32 {
+        self.curr_capacity
+    }
+
+    /// The resources that remain available on the server.
+    pub fn curr_resources(&self) -> Resources {
+        Resources {
+            containers: self.curr_capacity,
+            cpu: self.curr_cpu,
+            memory: self.curr_memory,
+        }
+    }
+
     pub fn conn_addr(&self) -> SocketAddr {
-        let server_addr = &self.server_info.server_addr;
-        SocketAddr::new(server_addr.conn_ip, server_addr.conn_port)
+        self.server_info.conn_addr()
+    }
+
+    /// The data and management IP addresses of the server.
+    pub fn ips(&self) -> (IpAddr, IpAddr) {
+        (self.server_info.server_addr.data_ip, self.server_info.server_addr.man_ip)
+    }
+
+    /// Return the resources taken by a removed device to the server.
+    pub fn release(&mut self, resource_size: Resources) {
+        self.curr_capacity = std::cmp::min(self.curr_capacity + resource_size.containers, self.allocated_capacity());
+        self.curr_cpu = std::cmp::min(self.curr_cpu.saturating_add(resource_size.cpu), self.allocated_cpu);
+        self.curr_memory = std::cmp::min(self.curr_memory.saturating_add(resource_size.memory), self.allocated_memory);
     }
 }
 
 impl PartitionBin for ContainerServer {
-    type Size = u32;
+    type Size = Resources;
     type BinId = Uuid;
 
-    fn fill(&mut self, resource_size: u32) -> bool {
-        if self.curr_capacity < resource_size {
+    fn fill(&mut self, resource_size: Resources) -> bool {
+        if !self.curr_resources().fits(&resource_size) {
             return false;
         }
         else {
-            self.curr_capacity -= resource_size;
+            self.curr_capacity -= resource_size.containers;
+            self.curr_cpu -= resource_size.cpu;
+            self.curr_memory -= resource_size.memory;
             return true;
         }
     }
@@ -165,4 +623,220 @@ impl PartitionBin for ContainerServer {
     fn bin_id(&self) -> Self::BinId {
         return self.id()
     }
-}
\ No newline at end of file
+
+    fn remaining(&self) -> Self::Size {
+        return self.curr_resources()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // servers with the given capacities, each with 1000 millicores and 1024 MiB per container
+    fn server_list(capacities: &[u32]) -> ServerInfoList {
+        let mut sp = ServerInfoList::new();
+        for (i, capacity) in capacities.iter().enumerate() {
+            sp.add_server_info(
+                &format!("127.0.0.{}", i + 1), 10000, &format!("10.0.0.{}", i + 1), &format!("10.1.0.{}", i + 1), 
+                *capacity, capacity * 1000, capacity * 1024
+            ).unwrap();
+        }
+        sp
+    }
+
+    // the containers taken from each server, in the order of the servers
+    fn shares(sp: &ServerInfoList, allocation: &Vec<ContainerServer>) -> Vec<u32> {
+        sp.servers.iter().map(|si| {
+            allocation.iter().find(|cs| {cs.id() == si.id}).map(|cs| {cs.curr_capacity()}).unwrap_or(0)
+        }).collect()
+    }
+
+    fn remaining(sp: &ServerInfoList) -> Vec<u32> {
+        sp.servers.iter().map(|si| {si.remaining_capacity()}).collect()
+    }
+
+    #[test]
+    fn allocate_across_servers() {
+        let mut sp = server_list(&[3, 5, 4]);
+        let alloc = sp.allocate_servers(7, AllocationPolicy::FirstFit).unwrap();
+        assert_eq!(shares(&sp, &alloc), vec!(3, 4, 0));
+        assert_eq!(remaining(&sp), vec!(0, 1, 4));
+
+        let mut sp = server_list(&[3, 5, 4]);
+        let alloc = sp.allocate_servers(7, AllocationPolicy::BestFit).unwrap();
+        assert_eq!(shares(&sp, &alloc), vec!(2, 5, 0));
+        assert_eq!(remaining(&sp), vec!(1, 0, 4));
+
+        let mut sp = server_list(&[3, 5, 4]);
+        let alloc = sp.allocate_servers(7, AllocationPolicy::Spread).unwrap();
+        assert_eq!(shares(&sp, &alloc), vec!(3, 2, 2));
+        assert_eq!(remaining(&sp), vec!(0, 3, 2));
+    }
+
+    #[test]
+    fn allocate_exact_fit() {
+        // best fit picks the tightest single server
+        let mut sp = server_list(&[5, 4, 6]);
+        let alloc = sp.allocate_servers(4, AllocationPolicy::BestFit).unwrap();
+        assert_eq!(shares(&sp, &alloc), vec!(0, 4, 0));
+
+        // the whole pool is used up by every policy
+        for policy in vec!(AllocationPolicy::FirstFit, AllocationPolicy::BestFit, AllocationPolicy::Spread).into_iter() {
+            let mut sp = server_list(&[5, 4, 6]);
+            let alloc = sp.allocate_servers(15, policy).unwrap();
+            assert_eq!(shares(&sp, &alloc), vec!(5, 4, 6));
+            assert_eq!(remaining(&sp), vec!(0, 0, 0));
+            assert_eq!(sp.allocate_servers(1, policy).unwrap_err(), 0);
+        }
+    }
+
+    #[test]
+    fn allocate_fails_without_enough_capacity() {
+        for policy in vec!(AllocationPolicy::FirstFit, AllocationPolicy::BestFit, AllocationPolicy::Spread).into_iter() {
+            let mut sp = server_list(&[3, 5, 4]);
+            sp.allocate_servers(4, policy).unwrap();
+            let before = remaining(&sp);
+
+            // the remaining capacity is reported, and the pool is unchanged
+            assert_eq!(sp.allocate_servers(9, policy).unwrap_err(), 8);
+            assert_eq!(remaining(&sp), before);
+
+            // draining and down servers have no remaining capacity
+            let (id0, id1) = (sp.servers[0].id, sp.servers[1].id);
+            sp.drain_server(id0, true).unwrap();
+            sp.set_health(id1, false, 0).unwrap();
+            assert_eq!(sp.allocate_servers(8, policy).unwrap_err(), before[2]);
+        }
+    }
+
+    #[test]
+    fn release_restores_the_pool() {
+        for policy in vec!(AllocationPolicy::FirstFit, AllocationPolicy::BestFit, AllocationPolicy::Spread).into_iter() {
+            let mut sp = server_list(&[3, 5, 4]);
+            let net0 = sp.allocate_servers(6, policy).unwrap();
+            let net1 = sp.allocate_servers(6, policy).unwrap();
+            assert_eq!(remaining(&sp), vec!(0, 0, 0));
+
+            sp.release_servers(net0).unwrap();
+            assert_eq!(remaining(&sp).iter().sum::<u32>(), 6);
+            sp.release_servers(net1).unwrap();
+            assert_eq!(remaining(&sp), vec!(3, 5, 4));
+            assert!(sp.servers.iter().all(|si| {(si.allocated, si.allocated_cpu, si.allocated_memory) == (0, 0, 0)}));
+        }
+    }
+
+    #[test]
+    fn release_adds_back_removed_servers() {
+        let mut sp = server_list(&[3, 5]);
+        let alloc = sp.allocate_servers(8, AllocationPolicy::FirstFit).unwrap();
+        sp.servers.remove(0);
+        sp.release_servers(alloc).unwrap();
+        assert_eq!(remaining(&sp), vec!(5, 3));
+    }
+
+    #[test]
+    fn stored_list_is_loaded_without_validation() {
+        // a list stored before the validation, two servers share a data IP
+        let mut servers = server_list(&[3, 5]).into_vec();
+        servers[1].server_addr.data_ip = servers[0].server_addr.data_ip;
+        assert_eq!(
+            ServerInfoList::from_iterator(servers.iter().cloned()).err(), 
+            Some(ServerRegistrationError::DuplicateDataIp(servers[0].server_addr.data_ip))
+        );
+        let mut sp = ServerInfoList::from_stored(servers.into_iter());
+        assert!(sp.allocate_servers(8, AllocationPolicy::FirstFit).is_ok());
+
+        // the new servers are still validated
+        let res = sp.add_server_info("127.0.0.9", 10000, "10.0.0.1", "10.1.0.9", 1, 1000, 1024);
+        assert_eq!(res.unwrap_err(), ServerRegistrationError::DuplicateDataIp("10.0.0.1".parse().unwrap()));
+        let res = sp.add_server_info("127.0.0.9", 0, "10.0.0.9", "10.1.0.9", 1, 1000, 1024);
+        assert_eq!(res.unwrap_err(), ServerRegistrationError::UnreachablePort(0));
+    }
+
+    #[test]
+    fn from_iterator_validates_each_server() {
+        let servers = server_list(&[3, 5]).into_vec();
+        assert!(ServerInfoList::from_iterator(servers.iter().cloned()).is_ok());
+
+        let mut unreachable = servers.clone();
+        unreachable[1].server_addr.conn_port = 0;
+        assert_eq!(ServerInfoList::from_iterator(unreachable.into_iter()).err(), Some(ServerRegistrationError::UnreachablePort(0)));
+        let mut empty = servers.clone();
+        empty[0].max_capacity = 0;
+        assert_eq!(ServerInfoList::from_iterator(empty.into_iter()).err(), Some(ServerRegistrationError::ZeroCapacity));
+        let mut duplicate = servers;
+        duplicate[1].server_addr.man_ip = duplicate[0].server_addr.man_ip;
+        assert_eq!(
+            ServerInfoList::from_iterator(duplicate.iter().cloned()).err(),
+            Some(ServerRegistrationError::DuplicateManIp(duplicate[0].server_addr.man_ip))
+        );
+    }
+
+    #[test]
+    fn reconcile_refuses_unsafe_changes() {
+        let mut sp = server_list(&[3, 5]);
+        sp.allocate_servers(5, AllocationPolicy::FirstFit).unwrap();
+        let before = remaining(&sp);
+
+        // the capacity can not be lowered below the allocated part
+        let res = sp.reconcile(server_list(&[3, 1]).into_vec());
+        assert_eq!(res.unwrap_err(), ServerRegistrationError::CapacityInUse("127.0.0.2:10000".parse().unwrap()));
+        assert_eq!(remaining(&sp), before);
+
+        // the data IP can not be changed
+        let mut configured = server_list(&[3, 5]).into_vec();
+        configured[0].server_addr.data_ip = "10.0.0.9".parse().unwrap();
+        let res = sp.reconcile(configured);
+        assert_eq!(res.unwrap_err(), ServerRegistrationError::AddressChanged("127.0.0.1:10000".parse().unwrap()));
+
+        // raising the capacity is fine
+        let report = sp.reconcile(server_list(&[3, 6]).into_vec()).unwrap();
+        assert_eq!(report.updated, vec!("127.0.0.2:10000".parse().unwrap()));
+        assert_eq!(remaining(&sp), vec!(0, 4));
+    }
+
+    #[test]
+    fn drained_server_can_be_undrained() {
+        let mut sp = server_list(&[3]);
+        let id = sp.servers[0].id;
+        sp.drain_server(id, true).unwrap();
+        assert_eq!(sp.allocate_servers(1, AllocationPolicy::FirstFit).unwrap_err(), 0);
+        sp.drain_server(id, false).unwrap();
+        assert!(sp.allocate_servers(1, AllocationPolicy::FirstFit).is_ok());
+    }
+
+    #[test]
+    fn remove_server_refuses_changed_allocation() {
+        let mut sp = server_list(&[3, 5]);
+        let id = sp.servers[0].id;
+        sp.allocate_servers(2, AllocationPolicy::FirstFit).unwrap();
+        assert!(sp.remove_server(id, 0).is_err());
+        assert!(sp.remove_server(id, 2).is_ok());
+        assert!(sp.remove_server(id, 2).is_err());
+    }
+
+    #[test]
+    fn cpu_and_memory_are_shared_among_emunets() {
+        let mut sp = server_list(&[4]);
+        let net0 = sp.allocate_servers(1, AllocationPolicy::FirstFit).unwrap();
+        let net1 = sp.allocate_servers(3, AllocationPolicy::FirstFit).unwrap();
+        assert_eq!(net0[0].curr_resources(), Resources { containers: 1, cpu: 1000, memory: 1024 });
+        assert_eq!(net1[0].curr_resources(), Resources { containers: 3, cpu: 3000, memory: 3072 });
+        assert_eq!((sp.servers[0].allocated_cpu, sp.servers[0].allocated_memory), (4000, 4096));
+
+        sp.release_servers(net0).unwrap();
+        assert_eq!((sp.servers[0].allocated_cpu, sp.servers[0].allocated_memory), (3000, 3072));
+        let net2 = sp.allocate_servers(1, AllocationPolicy::FirstFit).unwrap();
+        assert_eq!(net2[0].curr_resources(), Resources { containers: 1, cpu: 1000, memory: 1024 });
+    }
+
+    #[test]
+    fn unlimited_resources_are_not_divided() {
+        let mut sp = ServerInfoList::new();
+        sp.add_server_info("127.0.0.1", 10000, "10.0.0.1", "10.1.0.1", 4, u32::MAX, u32::MAX).unwrap();
+        let net = sp.allocate_servers(2, AllocationPolicy::FirstFit).unwrap();
+        assert_eq!(net[0].curr_resources(), Resources { containers: 2, cpu: u32::MAX, memory: u32::MAX });
+        assert_eq!((sp.servers[0].allocated_cpu, sp.servers[0].allocated_memory), (0, 0));
+    }
+}
@@ -31,6 +31,14 @@ impl EmuNetUser {
         self.emu_net_ids.get(emu_net_name).is_some()
     }
 
+    pub fn get_emu_net(&self, emu_net_name: &str) -> Option<Uuid> {
+        self.emu_net_ids.get(emu_net_name).map(|id| {id.clone()})
+    }
+
+    pub fn remove_emu_net(&mut self, emu_net_name: &str) -> Option<Uuid> {
+        self.emu_net_ids.remove(emu_net_name)
+    }
+
     pub fn get_all_emu_nets(&self) -> HashMap<String, Uuid> {
         return self.emu_net_ids.clone()
     }
@@ -5,9 +5,11 @@
 // mod enet;
 pub mod errors;
 pub mod container_backend;
+pub mod container_agent;
 pub mod backend;
 pub mod autogen;
 pub mod emunet;
 pub mod restful;
 pub mod algo;
-pub mod database;
\ No newline at end of file
+pub mod database;
+pub mod config;
\ No newline at end of file
@@ -0,0 +1,59 @@
+use warp::{http, Filter};
+use serde::{Serialize, Deserialize};
+
+use crate::database::{Client};
+
+// cpu and memory are not limited if they are not specified
+fn unlimited() -> u32 {
+    u32::MAX
+}
+
+#[derive(Deserialize)]
+struct Json {
+    conn_ip: String,
+    conn_port: u16,
+    data_ip: String,
+    man_ip: String,
+    max_capacity: u32,
+    #[serde(default = "unlimited")]
+    max_cpu: u32,
+    #[serde(default = "unlimited")]
+    max_memory: u32,
+}
+
+#[derive(Serialize)]
+struct Response {
+    server_uuid: uuid::Uuid,
+}
+
+async fn add_server(json: Json, db_client: Client) -> Result<impl warp::Reply, warp::Rejection> {
+    let server_uuid = extract_response!(
+        db_client.add_server(
+            &json.conn_ip, json.conn_port, &json.data_ip, &json.man_ip, 
+            json.max_capacity, json.max_cpu, json.max_memory
+        ).await,
+        "internal_server_error",
+        "operation_fail"
+    );
+
+    let resp = Response {server_uuid};
+    Ok(warp::reply::with_status(serde_json::to_string(&resp).unwrap(), http::StatusCode::OK))
+}
+
+/// This filter adds a new container server to the server pool at runtime.
+pub fn build_filter(db_client: Client) 
+    -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static
+{
+    let db_filter = warp::any().map(move || {
+        let clone = db_client.clone();
+        clone
+    });
+    warp::post()
+        .and(warp::path("v1"))
+        .and(warp::path("servers"))
+        .and(warp::path("add"))
+        .and(warp::path::end())
+        .and(super::parse_json_body())
+        .and(db_filter)
+        .and_then(add_server)
+}
\ No newline at end of file
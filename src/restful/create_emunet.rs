@@ -2,12 +2,15 @@ use warp::{http, Filter};
 use serde::{Serialize, Deserialize};
 
 use crate::database::{Client};
+use crate::emunet::server::AllocationPolicy;
 
 #[derive(Deserialize)]
 struct Json {
     user: String,
     emunet: String,
     capacity: u32,
+    #[serde(default)]
+    allocation: AllocationPolicy, // how to allocate the capacity from the servers
 }
 
 #[derive(Serialize)]
@@ -17,7 +20,7 @@ struct Response {
 
 async fn create_emunet(json_msg: Json, db_client: Client) -> Result<impl warp::Reply, warp::Rejection> {
     let emunet_uuid = extract_response!(
-        db_client.create_emu_net(json_msg.user, json_msg.emunet, json_msg.capacity).await,
+        db_client.create_emu_net(json_msg.user, json_msg.emunet, json_msg.capacity, json_msg.allocation).await,
         "internal_server_error",
         "operation_fail"
     ); 
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use warp::{http, Filter};
+use warp::reply::with_status;
+use http::StatusCode;
+use serde::{Serialize, Deserialize};
+use futures::future;
+
+use crate::database::{Client};
+use crate::container_backend::{self, ServerAssignment};
+use crate::emunet::net::*;
+use super::{emunet_error, store_emunet};
+
+#[derive(Deserialize)]
+struct Json {
+    user: String,
+    emunet: String,
+}
+
+#[derive(Serialize)]
+struct Response {
+    status: String,
+    emunet: String,
+}
+
+async fn delete_emunet(json_msg: Json, db_client: Client) -> Result<impl warp::Reply, warp::Rejection> {
+    // find the uuid of the emunet
+    let emunets = extract_response!(
+        db_client.list_emu_net_uuid(json_msg.user.clone()).await,
+        "internal_server_error",
+        "operation_fail"
+    );
+    let emunet_uuid = match emunets.get(&json_msg.emunet) {
+        Some(uuid) => uuid.clone(),
+        None => return Ok(with_status("{ \"operation_fail\": \"invalid emu-net name\" }".to_string(), StatusCode::BAD_REQUEST)),
+    };
+
+    // atomically move the emunet into working state, an emunet that is being 
+    // initialized or updated can not be deleted
+    let prev = extract_response!(
+        db_client.update_emu_net(emunet_uuid, |emunet| {
+            if emunet.is_working() {
+                return Err("emu-net is working, try again later".to_string());
+            }
+            let prev = emunet.clone();
+            emunet.working();
+            Ok(prev)
+        }).await,
+        "internal_server_error",
+        "operation_fail"
+    );
+    let mut emunet = prev.clone();
+    emunet.working();
+
+    // remove the devices from the servers, the links are removed together with the devices
+    let res = match db_client.get_emu_net_graph(&emunet).await {
+        Ok(Ok((vertexes, _))) => Ok(vertexes),
+        Ok(Err(err)) => Err(with_status(format!("{{ \"operation_fail\": \"{}\" }}", err), StatusCode::BAD_REQUEST)),
+        Err(err) => Err(with_status(format!("{{ \"internal_server_error\": \"{}\" }}", err), StatusCode::INTERNAL_SERVER_ERROR)),
+    };
+    let vertexes = match res {
+        Ok(vertexes) => vertexes,
+        Err(reply) => {
+            // nothing is removed yet, put the emunet back into its previous state
+            if let Err(err) = store_emunet(&db_client, prev).await {
+                log::error!("fail to restore emunet {}: {}", emunet_uuid, err);
+            }
+            return Ok(reply);
+        }
+    };
+    let mut assignments: HashMap<uuid::Uuid, ServerAssignment> = emunet.servers().fold(HashMap::new(), |mut map, cs| {
+        map.insert(cs.id(), ServerAssignment::new(cs.conn_addr()));
+        map
+    });
+    for v in vertexes.iter() {
+        assignments.get_mut(&v.server_uuid()).unwrap().add_device(v);
+    }
+    let remove_results = future::join_all(assignments.into_iter().filter(|(_, assignment)| {!assignment.is_empty()}).map(|(_, assignment)| {
+        container_backend::remove(emunet.vertex_type(), assignment)
+    })).await;
+    let failures: Vec<String> = remove_results.into_iter().filter_map(|res| {res.err()}).collect();
+    if !failures.is_empty() {
+        // keep the emunet and its servers, so that the deletion can be retried
+        let err = failures.join(", ");
+        emunet_error(db_client, emunet, EmuNetError::RemoveFail(err.clone())).await;
+        return Ok(with_status(format!("{{ \"operation_fail\": \"{}\" }}", err), StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    // the devices are gone, so the emunet is kept in error state if it can not be deleted
+    let res = db_client.delete_emu_net(json_msg.user, json_msg.emunet.clone()).await;
+    let failure = match res {
+        Ok(Ok(_)) => None,
+        Ok(Err(err)) => Some((format!("{{ \"operation_fail\": \"{}\" }}", err), StatusCode::BAD_REQUEST, err)),
+        Err(err) => Some((format!("{{ \"internal_server_error\": \"{}\" }}", err), StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err))),
+    };
+    if let Some((reply, status, err)) = failure {
+        emunet_error(db_client, emunet, EmuNetError::DatabaseFail(err)).await;
+        return Ok(with_status(reply, status));
+    }
+
+    let resp = Response {
+        status: "OK".to_string(),
+        emunet: json_msg.emunet,
+    };
+    Ok(warp::reply::with_status(serde_json::to_string(&resp).unwrap(), http::StatusCode::OK))
+}
+
+/// This filter tears down an existing emunet of a user.
+/// The devices are removed from the container servers first, then the emunet and all
+/// of its devices are removed from the database, and the servers allocated to the 
+/// emunet are returned to the server pool.
+/// If any step fails after the devices are removed, the emunet is kept in error state 
+/// and the deletion can be retried.
+pub fn build_filter(db_client: Client) 
+    -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static
+{
+    let db_filter = warp::any().map(move || {
+        let clone = db_client.clone();
+        clone
+    });
+    warp::post()
+        .and(warp::path("v1"))
+        .and(warp::path("delete_emunet"))
+        .and(warp::path::end())
+        .and(super::parse_json_body())
+        .and(db_filter)
+        .and_then(delete_emunet)
+}
+
+#[cfg(test)]
+mod tests {
+    use warp::Reply;
+
+    use super::*;
+    use crate::database::ClientLauncher;
+    use crate::emunet::server::{AllocationPolicy, ServerInfoList};
+
+    #[tokio::test]
+    async fn failed_deletion_can_be_retried() {
+        ClientLauncher::in_memory().with_db_client(|client| async move {
+            let mut sp = ServerInfoList::new();
+            sp.add_server_info("127.0.0.1", 10000, "10.0.0.1", "10.1.0.1", 10, 8000, 16384).unwrap();
+            client.init(sp.into_vec()).await.unwrap().unwrap();
+            client.register_user("user").await.unwrap().unwrap();
+            let uuid = client.create_emu_net("user".to_string(), "net".to_string(), 4, AllocationPolicy::FirstFit).await.unwrap().unwrap();
+            let json = || {Json { user: "user".to_string(), emunet: "net".to_string() }};
+
+            // the server of the emunet is replaced, so it can not be returned to the pool
+            let stored = client.list_servers().await.unwrap().unwrap();
+            let mut replaced = ServerInfoList::new();
+            replaced.add_server_info("127.0.0.1", 10000, "10.0.0.9", "10.1.0.9", 10, 8000, 16384).unwrap();
+            client.replace_server_info_list(replaced.into_vec()).await;
+            let resp = delete_emunet(json(), client.clone()).await.unwrap().into_response();
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            assert!(!client.get_emu_net(uuid).await.unwrap().unwrap().is_working());
+
+            // the deletion is retried once the server is back
+            client.replace_server_info_list(stored).await;
+            let resp = delete_emunet(json(), client.clone()).await.unwrap().into_response();
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert!(client.get_emu_net(uuid).await.unwrap().is_err());
+            assert_eq!(client.list_servers().await.unwrap().unwrap()[0].remaining_capacity(), 10);
+            Ok(())
+        }).await.unwrap();
+    }
+}
\ No newline at end of file
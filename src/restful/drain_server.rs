@@ -0,0 +1,57 @@
+use warp::{http, Filter};
+use serde::{Serialize, Deserialize};
+
+use crate::database::{Client};
+
+fn default_draining() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct Json {
+    server_uuid: uuid::Uuid,
+    #[serde(default = "default_draining")]
+    draining: bool, // false includes a drained server in new allocations again
+}
+
+#[derive(Serialize)]
+struct Response {
+    status: String,
+    server_uuid: uuid::Uuid,
+    draining: bool,
+}
+
+async fn drain_server(json: Json, db_client: Client) -> Result<impl warp::Reply, warp::Rejection> {
+    let _ = extract_response!(
+        db_client.drain_server(json.server_uuid.clone(), json.draining).await,
+        "internal_server_error",
+        "operation_fail"
+    );
+
+    let resp = Response {
+        status: "OK".to_string(),
+        server_uuid: json.server_uuid,
+        draining: json.draining,
+    };
+    Ok(warp::reply::with_status(serde_json::to_string(&resp).unwrap(), http::StatusCode::OK))
+}
+
+/// This filter excludes a container server from new allocations, the emunets
+/// that are already allocated on the server keep using it. With `draining` set
+/// to false, the server is included in new allocations again.
+pub fn build_filter(db_client: Client) 
+    -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static
+{
+    let db_filter = warp::any().map(move || {
+        let clone = db_client.clone();
+        clone
+    });
+    warp::post()
+        .and(warp::path("v1"))
+        .and(warp::path("servers"))
+        .and(warp::path("drain"))
+        .and(warp::path::end())
+        .and(super::parse_json_body())
+        .and(db_filter)
+        .and_then(drain_server)
+}
\ No newline at end of file
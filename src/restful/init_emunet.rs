@@ -4,12 +4,17 @@ use warp::{http, Filter};
 use warp::reply::with_status;
 use http::StatusCode;
 use serde::Deserialize;
-use tokio::time;
+use futures::future;
 
 use crate::database::{Client};
+use crate::container_backend::{self, ServerAssignment};
 use crate::emunet::net::*;
+use crate::emunet::server::{ContainerServer, Resources};
+use crate::emunet::ipam::{Ipam, IpamConfig};
+use crate::emunet::link_plan::{count_tunnels, plan_links};
 use crate::algo::in_memory_graph::InMemoryGraph;
-use crate::algo::Partition;
+use crate::algo::{check_partition, PartitionStrategy};
+use super::{emunet_error, release_tunnels, store_emunet};
 
 // format of the incoming json message
 #[derive(Deserialize)]
@@ -17,27 +22,31 @@ struct Json {
     emunet_uuid: uuid::Uuid, // uuid of the emunet object on the database
     devs: Vec<VertexInfo>, // a list of vertexes to be created
     links: Vec<EdgeInfo>, // a list of edges to be created
+    #[serde(default)]
+    strategy: PartitionStrategy, // how to place the devices on the servers
+    #[serde(default)]
+    ipam: IpamConfig, // the address pools of the links and devices
 }
 
-// given a client-side edge id, return the database-side edge uuid and the 
-// corresponding vertex to insert this directed edge
-// note: the caller should ensure that this function never panics the program
-fn insert_edge_helper<'a>(e_id: (u64, u64), id_map: &HashMap<u64, uuid::Uuid>, vertex_map: &'a mut HashMap<u64, Vertex>)
--> ((uuid::Uuid, uuid::Uuid), &'a mut Vertex)
-{
-    let e_uuid = (id_map.get(&e_id.0).unwrap().clone(), id_map.get(&e_id.1).unwrap().clone());
-    let vertex_mut = vertex_map.get_mut(&e_id.0).unwrap();
-    (e_uuid, vertex_mut)
-}
+// reject the devices that can never fit in the servers of the emunet
+fn check_resources(network_graph: &InMemoryGraph<u64, VertexInfo, EdgeInfo>, emunet: &EmuNet) -> Result<(), String> {
+    let mut total_demand = Resources { containers: 0, cpu: 0, memory: 0 };
+    let mut total_capacity = Resources { containers: 0, cpu: 0, memory: 0 };
+    for cs in emunet.servers() {
+        total_capacity.add(&cs.curr_resources());
+    }
 
-// helper function to update error state on the emunet object
-async fn emunet_error(client: Client, mut emunet: EmuNet, err: EmuNetError) {
-    emunet.error(err);
-    // store the error state in the database, panic the server program on failure
-    let res = client.set_emu_net(emunet).await.expect("this should not happen");
-    if res.is_err() {
-        panic!("this should never happen");
+    for (id, vi) in network_graph.vertexes() {
+        let demand = vi.resources();
+        if !emunet.servers().any(|cs| {cs.curr_resources().fits(&demand)}) {
+            return Err(format!("device {} does not fit in any server", id));
+        }
+        total_demand.add(&demand);
+    }
+    if !total_capacity.fits(&total_demand) {
+        return Err("the total resources required by the devices exceed the servers".to_string());
     }
+    Ok(())
 }
 
 // the actual work is done in a background task
@@ -45,15 +54,30 @@ async fn background_task(client: Client, mut emunet: EmuNet, network_graph: InMe
     // record the network size
     let size = network_graph.size() as u32;
     
-    // do the partition
-    let res = network_graph.partition(emunet.servers_mut());
+    // partition into a copy of the servers, which replaces the servers of the emunet
+    // only if the partition is valid
+    let servers: Vec<ContainerServer> = emunet.servers().cloned().collect();
+    let mut filled = servers.clone();
+
+    // do the partition with the strategy selected for the emunet
+    let strategy = emunet.partition_strategy();
+    let res = network_graph.partition_with(strategy, filled.iter_mut());
     if res.is_err() {
         // set the state of the emunet to fail
         let err = EmuNetError::PartitionFail(format!("{}", res.map(|_|{()}).unwrap_err()));
         emunet_error(client, emunet, err).await;
         return;
     }    
-    let assignment = res.unwrap();
+    let (assignment, cut_size) = res.unwrap();
+    let res = check_partition(network_graph.vertexes().map(|(id, vi)| {(id, vi.resources())}), &assignment, servers);
+    if res.is_err() {
+        let err = EmuNetError::PartitionFail(format!("invalid partition: {}", res.unwrap_err()));
+        emunet_error(client, emunet, err).await;
+        return;
+    }
+    emunet.release_servers();
+    emunet.add_servers(filled);
+    log::info!("emunet {}: {} links are placed across container servers with {:?} strategy", emunet.vertex_type(), cut_size, strategy);
     
     // create a vertex-id-to-uuid map
     // prepare the EdgeInfo list, which will be used later
@@ -78,20 +102,48 @@ async fn background_task(client: Client, mut emunet: EmuNet, network_graph: InMe
         }
         map
     });
-    // insert the edges into the vertexes
-    let _: Vec<_> = edge_infos.into_iter().map(|ei| {
-        // insert the edge with forward direction
+    // build up the edges, each undirected edge is stored as a single directed edge 
+    // in the database, following the direction of the client-side edge id
+    let mut edges: Vec<Edge> = edge_infos.into_iter().map(|ei| {
         let e_id = ei.edge_id();
-        let (e_uuid, vertex_mut) = insert_edge_helper(e_id, &id_map, &mut vertexes_map);
-        let edge = Edge::new(e_uuid, ei.description());
-        vertex_mut.add_edge(edge).unwrap();
-
-        // insert the edge with reverse direction
-        let e_id = ei.reverse_edge_id();
-        let (e_uuid, vertex_mut) = insert_edge_helper(e_id, &id_map, &mut vertexes_map);
-        let edge = Edge::new(e_uuid, ei.description());
-        vertex_mut.add_edge(edge).unwrap();
+        let e_uuid = (id_map.get(&e_id.0).unwrap().clone(), id_map.get(&e_id.1).unwrap().clone());
+        Edge::new(e_uuid, ei.description(), ei.attrs().clone(), ei.ports())
     }).collect();
+    // number the parallel edges so that each of them has a distinct key in the database
+    assign_link_indexes(std::iter::empty(), &mut edges);
+
+    // assign the addresses in the order of the client-side ids, 
+    // the capacity of the pools has been checked before
+    if let Some(ipam) = emunet.ipam_mut() {
+        let mut client_ids: Vec<u64> = vertexes_map.keys().cloned().collect();
+        client_ids.sort();
+        for client_id in client_ids.into_iter() {
+            vertexes_map.get_mut(&client_id).unwrap().set_loopback(ipam.alloc_loopback().unwrap());
+        }
+        for e in edges.iter_mut() {
+            e.set_addrs(ipam.alloc_link().unwrap());
+        }
+    }
+
+    // plan the realisation of the links, each link across servers takes a VNI from the pool
+    let server_of: HashMap<uuid::Uuid, uuid::Uuid> = vertexes_map.values().map(|v| {(v.uuid(), v.server_uuid())}).collect();
+    let res = client.allocate_vnis(emunet.uuid().clone(), count_tunnels(&edges, &server_of)).await;
+    let vnis = match res {
+        Ok(Ok(vnis)) => vnis,
+        Ok(Err(err)) => {
+            // set the state of the emunet to fail
+            emunet_error(client, emunet, EmuNetError::LinkPlanFail(err)).await;
+            return;
+        },
+        Err(err) => {
+            // set the state of the emunet to fail
+            let err = EmuNetError::DatabaseFail(format!("{:?}", err));
+            emunet_error(client, emunet, err).await;
+            return;
+        }
+    };
+    let tunnels = plan_links(&mut edges, &server_of, &emunet.server_data_ips(), vnis.clone());
+    emunet.add_tunnels(tunnels);
 
     // create the vertexes in the database
     let res = client.bulk_create_vertexes(vertexes_map.values().map(|v|{v.uuid()}), emunet.vertex_type()).await;
@@ -100,6 +152,7 @@ async fn background_task(client: Client, mut emunet: EmuNet, network_graph: InMe
         Err(err) => {
             // set the state of the emunet to fail
             let err = EmuNetError::DatabaseFail(format!("{:?}", err));
+            release_tunnels(&client, &mut emunet, vnis).await;
             emunet_error(client, emunet, err).await;
             return;
         }
@@ -118,17 +171,102 @@ async fn background_task(client: Client, mut emunet: EmuNet, network_graph: InMe
         Err(err) => {
             // set the state of the emunet to fail
             let err = EmuNetError::DatabaseFail(format!("{:?}", err));
+            release_tunnels(&client, &mut emunet, vnis).await;
+            emunet_error(client, emunet, err).await;
+            return;
+        }
+    };
+
+    // create the edges in the database
+    let res = client.bulk_create_edges(edges.iter().map(|e|{e.edge_key(&emunet.vertex_type())})).await;
+    match res {
+        Ok(_) => {},
+        Err(err) => {
+            // set the state of the emunet to fail
+            let err = EmuNetError::DatabaseFail(format!("{:?}", err));
+            release_tunnels(&client, &mut emunet, vnis).await;
+            emunet_error(client, emunet, err).await;
+            return;
+        }
+    };
+
+    // set the edge properties
+    let res = client.bulk_set_edge_properties(
+        edges.iter().map(
+            |e| {
+                (e.edge_key(&emunet.vertex_type()), serde_json::to_value(e).unwrap())
+            }
+        )
+    ).await;
+    match res {
+        Ok(_) => {},
+        Err(err) => {
+            // set the state of the emunet to fail
+            let err = EmuNetError::DatabaseFail(format!("{:?}", err));
+            release_tunnels(&client, &mut emunet, vnis).await;
             emunet_error(client, emunet, err).await;
             return;
         }
     };
 
-    // emulate the background task of launching containers and creating connections
-    time::delay_for(time::Duration::new(5,0)).await;
-    // potentially perform an update on the vertexes
+    // build up the assignment of each container server, a link is assigned to 
+    // the servers of both of its end devices
+    let mut assignments: HashMap<uuid::Uuid, ServerAssignment> = emunet.servers().fold(HashMap::new(), |mut map, cs| {
+        map.insert(cs.id(), ServerAssignment::new(cs.conn_addr()));
+        map
+    });
+    let server_map: HashMap<uuid::Uuid, uuid::Uuid> = vertexes_map.values().fold(HashMap::new(), |mut map, v| {
+        assignments.get_mut(&v.server_uuid()).unwrap().add_device(v);
+        map.insert(v.uuid(), v.server_uuid());
+        map
+    });
+    for e in edges.iter() {
+        let edge_uuid = e.edge_uuid();
+        let servers = (server_map.get(&edge_uuid.0).unwrap(), server_map.get(&edge_uuid.1).unwrap());
+        assignments.get_mut(servers.0).unwrap().add_link(e);
+        if servers.0 != servers.1 {
+            assignments.get_mut(servers.1).unwrap().add_link(e);
+        }
+    }
+
+    // launch the containers and create the links on all the servers concurrently
+    let launch_results = future::join_all(assignments.into_iter().map(|(_, assignment)| {
+        container_backend::launch(emunet.vertex_type(), assignment)
+    })).await;
 
-    // set the state of the emunet to normal
-    emunet.normal();
+    // record the launch result of each device
+    let client_ids: HashMap<uuid::Uuid, u64> = id_map.iter().map(|(client_id, uuid)| {(uuid.clone(), *client_id)}).collect();
+    let mut failed_devs = 0;
+    for (vertex_uuid, state) in launch_results.into_iter().flatten() {
+        if let DeviceState::Failed(_) = state {
+            failed_devs += 1;
+        }
+        vertexes_map.get_mut(client_ids.get(&vertex_uuid).unwrap()).unwrap().set_state(state);
+    }
+    let res = client.bulk_set_vertex_properties(
+        vertexes_map.values().map(
+            |v| {
+                (v.uuid(), serde_json::to_value(v).unwrap())
+            }
+        )
+    ).await;
+    match res {
+        Ok(_) => {},
+        Err(err) => {
+            // set the state of the emunet to fail
+            let err = EmuNetError::DatabaseFail(format!("{:?}", err));
+            emunet_error(client, emunet, err).await;
+            return;
+        }
+    };
+
+    // set the state of the emunet to normal if all the devices are running
+    if failed_devs == 0 {
+        emunet.normal();
+    }
+    else {
+        emunet.error(EmuNetError::LaunchFail(format!("{} devices fail to launch", failed_devs)));
+    }
     // store the vertex mappings in to the emunet
     id_map.into_iter().fold(&mut emunet, |emunet, mapping| {
         emunet.add_vertex(mapping.0, mapping.1);
@@ -137,10 +275,10 @@ async fn background_task(client: Client, mut emunet: EmuNet, network_graph: InMe
     // reserve the capacity for the emunet
     emunet.reserve_capacity(size);
             
-    // store the state in the database, panic the server program on failure
-    let res = client.set_emu_net(emunet).await.unwrap();
-    if res.is_err() {
-        panic!("this should never happen");
+    // store the state in the database
+    let uuid = emunet.uuid().clone();
+    if let Err(err) = store_emunet(&client, emunet).await {
+        log::error!("fail to store emunet {}: {}", uuid, err);
     }
 }
 
@@ -157,6 +295,19 @@ async fn init_emunet(json: Json, db_client: Client) -> Result<impl warp::Reply,
         return Ok(with_status("{ \"operation_fail\": \"EmuNet can only be initialized once\"}".to_string(), StatusCode::BAD_REQUEST));
     };
 
+    // validate the device and link attributes
+    for vi in json.devs.iter() {
+        if let Err(err) = vi.validate() {
+            return Ok(with_status(format!("\"invalid_input_graph\": \"device {}: {}\"", vi.id(), err), StatusCode::BAD_REQUEST));
+        }
+    }
+    for ei in json.links.iter() {
+        if let Err(err) = ei.attrs().validate() {
+            let eid = ei.edge_id();
+            return Ok(with_status(format!("\"invalid_input_graph\": \"link ({}, {}): {}\"", eid.0, eid.1, err), StatusCode::BAD_REQUEST));
+        }
+    }
+
     // build up the in memory graph
     let res = InMemoryGraph::from_vecs(
         json.devs.into_iter().map(|v|{(v.id(), v)}).collect(), 
@@ -167,19 +318,55 @@ async fn init_emunet(json: Json, db_client: Client) -> Result<impl warp::Reply,
         // map it to () and then extract the error message
         return Ok(with_status(format!("\"invalid_input_graph\": \"{}\"", res.map(|_|{()}).unwrap_err()), StatusCode::BAD_REQUEST));
     }
-    let network_graph: InMemoryGraph<u64, VertexInfo, EdgeInfo> = res.unwrap();
+    let mut network_graph: InMemoryGraph<u64, VertexInfo, EdgeInfo> = res.unwrap();
     if network_graph.size() > emunet.capacity() as usize {
         // report error if the input network topology exceeds the capacity 
         // of the emunet
         return Ok(with_status(format!("\"invalid_input_graph\": \"input graph exceeds capacity limitation\""), StatusCode::BAD_REQUEST));
     }
+    let res = check_ports(network_graph.vertexes().map(|(_, vi)| {vi}), network_graph.edges().map(|(eid, _)| {*eid}));
+    if res.is_err() {
+        // report error if any device has more links than its interfaces
+        return Ok(with_status(format!("\"invalid_input_graph\": \"{}\"", res.unwrap_err()), StatusCode::BAD_REQUEST));
+    }
+    let mut ports = PortAllocator::new();
+    for (id, vi) in network_graph.vertexes() {
+        ports.add_device(*id, vi.total_ports());
+    }
+    let res = ports.assign(network_graph.edges_mut().map(|(_, ei)| {ei}));
+    if res.is_err() {
+        // report error if the interfaces specified by the links are invalid
+        return Ok(with_status(format!("\"invalid_input_graph\": \"{}\"", res.unwrap_err()), StatusCode::BAD_REQUEST));
+    }
+    let res = check_resources(&network_graph, &emunet);
+    if res.is_err() {
+        // report error if the devices can not fit in the servers of the emunet
+        return Ok(with_status(format!("\"invalid_input_graph\": \"{}\"", res.unwrap_err()), StatusCode::BAD_REQUEST));
+    }
+
+    // prepare the address pools, the addresses of the servers are excluded
+    let res = Ipam::new(&json.ipam, emunet.server_ipv4s()).and_then(|ipam| {
+        ipam.check_capacity(network_graph.edges().count(), network_graph.size()).map(|_| {ipam})
+    });
+    if res.is_err() {
+        return Ok(with_status(format!("\"invalid_ipam\": \"{}\"", res.unwrap_err()), StatusCode::BAD_REQUEST));
+    }
+    emunet.set_ipam(res.unwrap());
     
-    // update the state of the emunet object into working
+    // update the state of the emunet object into working, a concurrent initialization
+    // may have taken the emunet after it is retrieved
+    emunet.set_partition_strategy(json.strategy);
     emunet.working();
     let _ = extract_response!(
-        db_client.set_emu_net(emunet.clone()).await,
+        db_client.update_emu_net(emunet.uuid().clone(), |stored| {
+            if !stored.is_uninit() {
+                return Err("EmuNet can only be initialized once".to_string());
+            }
+            *stored = emunet.clone();
+            Ok(())
+        }).await,
         "internal_server_error",
-        "fatal(this-should-never-happen)"
+        "operation_fail"
     );
     
     // do the actual initialization work in the background
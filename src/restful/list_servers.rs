@@ -0,0 +1,39 @@
+use warp::{http, Filter};
+use serde::Serialize;
+
+use crate::database::{Client};
+use crate::emunet::server::ServerInfo;
+
+#[derive(Serialize)]
+struct Response {
+    servers: Vec<ServerInfo>,
+}
+
+async fn list_servers(db_client: Client) -> Result<impl warp::Reply, warp::Rejection> {
+    let servers = extract_response!(
+        db_client.list_servers().await,
+        "internal_server_error",
+        "operation_fail"
+    );
+
+    let resp = Response {servers};
+    Ok(warp::reply::with_status(serde_json::to_string(&resp).unwrap(), http::StatusCode::OK))
+}
+
+/// This filter lists all the container servers in the server pool, 
+/// together with their allocated capacity.
+pub fn build_filter(db_client: Client) 
+    -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static
+{
+    let db_filter = warp::any().map(move || {
+        let clone = db_client.clone();
+        clone
+    });
+    warp::get()
+        .and(warp::path("v1"))
+        .and(warp::path("servers"))
+        .and(warp::path("list"))
+        .and(warp::path::end())
+        .and(db_filter)
+        .and_then(list_servers)
+}
\ No newline at end of file
@@ -32,6 +32,9 @@ macro_rules! extract_response {
 use warp::Filter;
 use serde::de::DeserializeOwned;
 
+use crate::database::Client;
+use crate::emunet::net::{EmuNet, EmuNetError};
+
 // parse the input JSON message
 //
 // Note: when accepting a body, we want a JSON body and reject huge payloads
@@ -39,9 +42,57 @@ fn parse_json_body<T: DeserializeOwned + Send>() -> impl Filter<Extract = (T,),
     warp::body::content_length_limit(1024 * 16).and(warp::body::json())
 }
 
+// Store an emunet that the caller has moved into working state.
+//
+// A working emunet is only written by the task that moves it into working state, 
+// so the stored emunet is replaced only if it is still working.
+async fn store_emunet(client: &Client, emunet: EmuNet) -> Result<(), String> {
+    let res = client.update_emu_net(emunet.uuid().clone(), |stored| {
+        if !stored.is_working() {
+            return Err("emu-net is not in working state".to_string());
+        }
+        *stored = emunet;
+        Ok(())
+    }).await;
+    match res {
+        Ok(res) => res,
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
+// helper function to update error state on the emunet object
+async fn emunet_error(client: Client, mut emunet: EmuNet, err: EmuNetError) {
+    emunet.error(err);
+    let uuid = emunet.uuid().clone();
+    if let Err(err) = store_emunet(&client, emunet).await {
+        log::error!("fail to store the error state of emunet {}: {}", uuid, err);
+    }
+}
+
+// Take the planned tunnels away from the emunet and return their `vnis` to the pool.
+//
+// This is used when the update fails before the tunnels are created on the servers.
+async fn release_tunnels(client: &Client, emunet: &mut EmuNet, vnis: Vec<u32>) {
+    for vni in vnis.iter() {
+        emunet.remove_tunnel(*vni);
+    }
+    let res = client.release_vnis(vnis).await;
+    match res {
+        Ok(Ok(_)) => {},
+        Ok(Err(err)) => log::error!("fail to release the VNIs of emunet {}: {}", emunet.uuid(), err),
+        Err(err) => log::error!("fail to release the VNIs of emunet {}: {}", emunet.uuid(), err),
+    }
+}
+
 
 pub mod register_user;
 pub mod create_emunet;
 pub mod init_emunet;
+pub mod update_emunet;
 pub mod list_emunet;
-pub mod get_emunet;
\ No newline at end of file
+pub mod get_emunet;
+pub mod delete_emunet;
+pub mod add_server;
+pub mod list_servers;
+pub mod drain_server;
+pub mod remove_server;
\ No newline at end of file
@@ -0,0 +1,48 @@
+use warp::{http, Filter};
+use serde::{Serialize, Deserialize};
+
+use crate::database::{Client};
+
+#[derive(Deserialize)]
+struct Json {
+    server_uuid: uuid::Uuid,
+}
+
+#[derive(Serialize)]
+struct Response {
+    status: String,
+    server_uuid: uuid::Uuid,
+}
+
+async fn remove_server(json: Json, db_client: Client) -> Result<impl warp::Reply, warp::Rejection> {
+    let _ = extract_response!(
+        db_client.remove_server(json.server_uuid.clone()).await,
+        "internal_server_error",
+        "operation_fail"
+    );
+
+    let resp = Response {
+        status: "OK".to_string(),
+        server_uuid: json.server_uuid,
+    };
+    Ok(warp::reply::with_status(serde_json::to_string(&resp).unwrap(), http::StatusCode::OK))
+}
+
+/// This filter removes a container server from the server pool. The removal
+/// is refused if the server is still used by any emunet.
+pub fn build_filter(db_client: Client) 
+    -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static
+{
+    let db_filter = warp::any().map(move || {
+        let clone = db_client.clone();
+        clone
+    });
+    warp::post()
+        .and(warp::path("v1"))
+        .and(warp::path("servers"))
+        .and(warp::path("remove"))
+        .and(warp::path::end())
+        .and(super::parse_json_body())
+        .and(db_filter)
+        .and_then(remove_server)
+}
\ No newline at end of file
@@ -0,0 +1,739 @@
+use std::collections::{HashMap, HashSet};
+
+use warp::{http, Filter};
+use warp::reply::with_status;
+use http::StatusCode;
+use serde::Deserialize;
+use futures::future;
+use uuid::Uuid;
+
+use crate::database::{Client};
+use crate::container_backend::{self, ServerAssignment};
+use crate::emunet::net::*;
+use crate::emunet::link_plan::{count_tunnels, plan_links, LinkRealization};
+use crate::algo::in_memory_graph::InMemoryGraph;
+use crate::algo::PartitionBin;
+use super::{emunet_error, release_tunnels, store_emunet};
+
+// format of the incoming json message
+#[derive(Deserialize)]
+struct Json {
+    emunet_uuid: uuid::Uuid, // uuid of the emunet object on the database
+    #[serde(default)]
+    add_devs: Vec<VertexInfo>, // a list of vertexes to be created
+    #[serde(default)]
+    add_links: Vec<EdgeInfo>, // a list of edges to be created
+    #[serde(default)]
+    del_devs: Vec<u64>, // client side ids of the vertexes to be removed
+    #[serde(default)]
+    del_links: Vec<LinkRef>, // the edges to be removed
+}
+
+// reference to a stored link, the interfaces are needed to pick one of the parallel links
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LinkRef {
+    Ports { edge_id: (u64, u64), ports: (u32, u32) },
+    Pair((u64, u64)),
+}
+
+// find the position of the link referred to by `link` in `edges`, in either direction
+fn find_link(edges: &Vec<((u64, u64), Edge)>, link: &LinkRef) -> Result<usize, String> {
+    let eid = match link {
+        LinkRef::Ports { edge_id, .. } => *edge_id,
+        LinkRef::Pair(edge_id) => *edge_id,
+    };
+    let found: Vec<usize> = edges.iter().enumerate().filter(|(_, (id, e))| {
+        match link {
+            LinkRef::Ports { ports, .. } => {
+                (*id == eid && e.ports() == Some(*ports)) || (*id == (eid.1, eid.0) && e.ports() == Some((ports.1, ports.0)))
+            },
+            LinkRef::Pair(_) => *id == eid || *id == (eid.1, eid.0),
+        }
+    }).map(|(idx, _)| {idx}).collect();
+    match found.len() {
+        0 => Err(format!("link ({}, {}) does not exist", eid.0, eid.1)),
+        1 => Ok(found[0]),
+        _ => Err(format!("link ({}, {}) has parallel links, specify its ports", eid.0, eid.1)),
+    }
+}
+
+// the validated modification of the emunet topology
+struct Diff {
+    del_devs: Vec<Vertex>, // the stored vertexes to be removed
+    del_links: Vec<Edge>, // the stored edges to be removed, whose end vertexes are kept
+    dropped_links: Vec<Edge>, // the stored edges removed together with their end vertexes
+    kept_links: Vec<Edge>, // the stored edges that are kept
+    released_vnis: Vec<u32>, // the VNIs of the removed tunnels
+    add_devs: Vec<(VertexInfo, Uuid)>, // the new vertexes and the servers they are placed on
+    add_links: Vec<EdgeInfo>,
+    placement: HashMap<u64, (Uuid, Uuid)>, // client side id -> (vertex uuid, server uuid) of the kept vertexes
+}
+
+// Validate the modification against the stored graph, and place the new devices on the
+// servers of the emunet.
+//
+// The resources of the removed devices are returned to the servers before the placement,
+// and a new device is placed on the server holding most of its neighbours that it fits in.
+fn build_diff(mut json: Json, vertexes: Vec<Vertex>, edges: Vec<Edge>, emunet: &mut EmuNet) -> Result<Diff, String> {
+    let client_ids: HashMap<Uuid, u64> = vertexes.iter().map(|v| {(v.uuid(), v.id())}).collect();
+    let mut vertex_map: HashMap<u64, Vertex> = vertexes.into_iter().map(|v| {(v.id(), v)}).collect();
+    // the parallel links share the same client side id
+    let mut edge_list: Vec<((u64, u64), Edge)> = edges.into_iter().map(|e| {
+        let edge_uuid = e.edge_uuid();
+        ((*client_ids.get(&edge_uuid.0).unwrap(), *client_ids.get(&edge_uuid.1).unwrap()), e)
+    }).collect();
+
+    // remove the devices together with the links attached to them
+    let mut del_devs = Vec::new();
+    for id in json.del_devs.iter() {
+        let v = vertex_map.remove(id).ok_or(format!("device {} does not exist", id))?;
+        del_devs.push(v);
+    }
+    let removed: HashSet<u64> = json.del_devs.iter().cloned().collect();
+    let mut del_links = Vec::new();
+    let mut dropped_links = Vec::new(); // the links removed together with their devices
+    for link in json.del_links.iter() {
+        let (eid, e) = edge_list.remove(find_link(&edge_list, link)?);
+        if !removed.contains(&eid.0) && !removed.contains(&eid.1) {
+            del_links.push(e);
+        }
+        else {
+            dropped_links.push(e);
+        }
+    }
+    let (dropped, kept): (Vec<_>, Vec<_>) = edge_list.into_iter().partition(|(eid, _)| {
+        removed.contains(&eid.0) || removed.contains(&eid.1)
+    });
+    dropped_links.extend(dropped.into_iter().map(|(_, e)| {e}));
+    let edge_list = kept;
+
+    // validate the resulting topology and the attributes of the new devices and links
+    for vi in json.add_devs.iter() {
+        vi.validate().map_err(|err| {format!("device {}: {}", vi.id(), err)})?;
+    }
+    for ei in json.add_links.iter() {
+        let eid = ei.edge_id();
+        ei.attrs().validate().map_err(|err| {format!("link ({}, {}): {}", eid.0, eid.1, err)})?;
+    }
+    let res = InMemoryGraph::<u64, (), ()>::from_vecs(
+        vertex_map.keys().cloned().chain(json.add_devs.iter().map(|vi| {vi.id()})).map(|id| {(id, ())}).collect(),
+        edge_list.iter().map(|(eid, _)| {*eid}).chain(json.add_links.iter().map(|ei| {ei.edge_id()})).map(|eid| {(eid, ())}).collect(),
+    );
+    if res.is_err() {
+        return Err(res.map(|_|{()}).unwrap_err());
+    }
+    let kept_devs: Vec<VertexInfo> = vertex_map.values().map(|v| {v.vertex_info()}).collect();
+    check_ports(
+        kept_devs.iter().chain(json.add_devs.iter()),
+        edge_list.iter().map(|(eid, _)| {*eid}).chain(json.add_links.iter().map(|ei| {ei.edge_id()})),
+    )?;
+
+    // assign the interfaces of the new links, the interfaces of the kept links are taken first
+    let mut ports = PortAllocator::new();
+    for vi in kept_devs.iter().chain(json.add_devs.iter()) {
+        ports.add_device(vi.id(), vi.total_ports());
+    }
+    for (eid, e) in edge_list.iter() {
+        if let Some((port0, port1)) = e.ports() {
+            ports.take(eid.0, port0)?;
+            ports.take(eid.1, port1)?;
+        }
+    }
+    ports.assign(json.add_links.iter_mut())?;
+    if json.add_devs.len() > emunet.capacity() as usize + del_devs.len() {
+        return Err("the updated graph exceeds capacity limitation".to_string());
+    }
+
+    // return the addresses of the removed devices and links to the pools
+    if let Some(ipam) = emunet.ipam_mut() {
+        for v in del_devs.iter() {
+            if let Some(loopback) = v.loopback() {
+                ipam.release_loopback(loopback);
+            }
+        }
+        for e in del_links.iter().chain(dropped_links.iter()) {
+            if let Some(addrs) = e.addrs() {
+                ipam.release_link(addrs);
+            }
+        }
+        ipam.check_capacity(json.add_links.len(), json.add_devs.len())?;
+    }
+
+    // remove the tunnels of the removed links
+    let mut released_vnis = Vec::new();
+    for e in del_links.iter().chain(dropped_links.iter()) {
+        if let Some(LinkRealization::Vxlan { vni, .. }) = e.realization() {
+            emunet.remove_tunnel(*vni);
+            released_vnis.push(*vni);
+        }
+    }
+
+    // return the resources of the removed devices to their servers
+    for v in del_devs.iter() {
+        let server_uuid = v.server_uuid();
+        if let Some(cs) = emunet.servers_mut().find(|cs| {cs.id() == server_uuid}) {
+            cs.release(v.resources());
+        }
+    }
+
+    // place the new devices in the order of their ids
+    let mut server_of: HashMap<u64, Uuid> = vertex_map.values().map(|v| {(v.id(), v.server_uuid())}).collect();
+    let mut add_devs: Vec<VertexInfo> = json.add_devs;
+    add_devs.sort_by_key(|vi| {vi.id()});
+    let mut placed = Vec::new();
+    for vi in add_devs.into_iter() {
+        let mut neighbour_count: HashMap<Uuid, usize> = HashMap::new();
+        for ei in json.add_links.iter() {
+            let (id0, id1) = ei.edge_id();
+            let neighbour = if id0 == vi.id() { id1 } else if id1 == vi.id() { id0 } else { continue };
+            if let Some(server_uuid) = server_of.get(&neighbour) {
+                *neighbour_count.entry(*server_uuid).or_insert(0) += 1;
+            }
+        }
+
+        let demand = vi.resources();
+        let cs = emunet.servers_mut().filter(|cs| {cs.curr_resources().fits(&demand)}).max_by_key(|cs| {
+            (neighbour_count.get(&cs.id()).cloned().unwrap_or(0), cs.curr_capacity())
+        }).ok_or(format!("device {} does not fit in any server", vi.id()))?;
+        cs.fill(demand);
+        server_of.insert(vi.id(), cs.id());
+        placed.push((vi, cs.id()));
+    }
+
+    Ok(Diff {
+        del_devs,
+        del_links,
+        dropped_links,
+        kept_links: edge_list.into_iter().map(|(_, e)| {e}).collect(),
+        released_vnis,
+        add_devs: placed,
+        add_links: json.add_links,
+        placement: vertex_map.into_iter().map(|(id, v)| {(id, (v.uuid(), v.server_uuid()))}).collect(),
+    })
+}
+
+// the actual work is done in a background task
+async fn background_task(client: Client, mut emunet: EmuNet, diff: Diff) {
+    let mut failures = Vec::new();
+
+    // remove the links and the devices from the servers, a link is removed from
+    // the servers of both of its end devices
+    let mut assignments: HashMap<Uuid, ServerAssignment> = emunet.servers().fold(HashMap::new(), |mut map, cs| {
+        map.insert(cs.id(), ServerAssignment::new(cs.conn_addr()));
+        map
+    });
+    for v in diff.del_devs.iter() {
+        assignments.get_mut(&v.server_uuid()).unwrap().add_device(v);
+    }
+    let server_uuids: HashMap<Uuid, Uuid> = diff.placement.values().cloned().collect();
+    for e in diff.del_links.iter() {
+        let edge_uuid = e.edge_uuid();
+        let servers = (server_uuids.get(&edge_uuid.0).unwrap(), server_uuids.get(&edge_uuid.1).unwrap());
+        assignments.get_mut(servers.0).unwrap().add_link(e);
+        if servers.0 != servers.1 {
+            assignments.get_mut(servers.1).unwrap().add_link(e);
+        }
+    }
+    // a removed device takes its links away from its own server, the link to a kept
+    // device on another server is removed from the server of the kept device
+    let removed_servers: HashMap<Uuid, Uuid> = diff.del_devs.iter().map(|v| {(v.uuid(), v.server_uuid())}).collect();
+    for e in diff.dropped_links.iter() {
+        let edge_uuid = e.edge_uuid();
+        for (kept, removed) in vec!((edge_uuid.0, edge_uuid.1), (edge_uuid.1, edge_uuid.0)).into_iter() {
+            if let (Some(server), Some(peer_server)) = (server_uuids.get(&kept), removed_servers.get(&removed)) {
+                if server != peer_server {
+                    assignments.get_mut(server).unwrap().add_link(e);
+                }
+            }
+        }
+    }
+    let remove_results = future::join_all(assignments.into_iter().filter(|(_, assignment)| {!assignment.is_empty()}).map(|(_, assignment)| {
+        container_backend::remove(emunet.vertex_type(), assignment)
+    })).await;
+    failures.extend(remove_results.into_iter().filter_map(|res| {res.err()}));
+
+    // remove the devices and the links from the database
+    let res = client.bulk_delete_vertexes(diff.del_devs.iter().map(|v| {v.uuid()})).await;
+    if let Err(err) = res {
+        let err = EmuNetError::DatabaseFail(format!("{:?}", err));
+        emunet_error(client, emunet, err).await;
+        return;
+    }
+    let res = client.bulk_delete_edges(diff.del_links.iter().map(|e| {e.edge_key(&emunet.vertex_type())})).await;
+    if let Err(err) = res {
+        let err = EmuNetError::DatabaseFail(format!("{:?}", err));
+        emunet_error(client, emunet, err).await;
+        return;
+    }
+    for v in diff.del_devs.iter() {
+        emunet.remove_vertex(v.id());
+    }
+    let res = client.release_vnis(diff.released_vnis).await;
+    if let Err(err) = res {
+        let err = EmuNetError::DatabaseFail(format!("{:?}", err));
+        emunet_error(client, emunet, err).await;
+        return;
+    }
+
+    // build up the new vertexes and edges
+    let mut placement = diff.placement;
+    let mut vertexes_map: HashMap<Uuid, Vertex> = diff.add_devs.into_iter().fold(HashMap::new(), |mut map, (vi, server_uuid)| {
+        let uuid = indradb::util::generate_uuid_v1();
+        placement.insert(vi.id(), (uuid, server_uuid));
+        map.insert(uuid, Vertex::new(vi, uuid, server_uuid));
+        map
+    });
+    let mut edges: Vec<Edge> = diff.add_links.into_iter().map(|ei| {
+        let e_id = ei.edge_id();
+        let e_uuid = (placement.get(&e_id.0).unwrap().0, placement.get(&e_id.1).unwrap().0);
+        Edge::new(e_uuid, ei.description(), ei.attrs().clone(), ei.ports())
+    }).collect();
+    // the indexes of the kept parallel edges are still in use in the database
+    assign_link_indexes(diff.kept_links.iter(), &mut edges);
+
+    // assign the addresses, the capacity of the pools has been checked before
+    if let Some(ipam) = emunet.ipam_mut() {
+        let mut new_devs: Vec<&mut Vertex> = vertexes_map.values_mut().collect();
+        new_devs.sort_by_key(|v| {v.id()});
+        for v in new_devs.into_iter() {
+            v.set_loopback(ipam.alloc_loopback().unwrap());
+        }
+        for e in edges.iter_mut() {
+            e.set_addrs(ipam.alloc_link().unwrap());
+        }
+    }
+
+    // plan the realisation of the new links, each link across servers takes a VNI from the pool
+    let server_uuids: HashMap<Uuid, Uuid> = placement.values().cloned().collect();
+    let res = client.allocate_vnis(emunet.uuid().clone(), count_tunnels(&edges, &server_uuids)).await;
+    let vnis = match res {
+        Ok(Ok(vnis)) => vnis,
+        Ok(Err(err)) => {
+            emunet_error(client, emunet, EmuNetError::LinkPlanFail(err)).await;
+            return;
+        },
+        Err(err) => {
+            let err = EmuNetError::DatabaseFail(format!("{:?}", err));
+            emunet_error(client, emunet, err).await;
+            return;
+        }
+    };
+    let tunnels = plan_links(&mut edges, &server_uuids, &emunet.server_data_ips(), vnis.clone());
+    emunet.add_tunnels(tunnels);
+
+    // create the vertexes and edges in the database
+    let res = client.bulk_create_vertexes(vertexes_map.keys().cloned(), emunet.vertex_type()).await;
+    if let Err(err) = res {
+        let err = EmuNetError::DatabaseFail(format!("{:?}", err));
+        release_tunnels(&client, &mut emunet, vnis).await;
+        emunet_error(client, emunet, err).await;
+        return;
+    }
+    let res = client.bulk_create_edges(edges.iter().map(|e|{e.edge_key(&emunet.vertex_type())})).await;
+    if let Err(err) = res {
+        let err = EmuNetError::DatabaseFail(format!("{:?}", err));
+        release_tunnels(&client, &mut emunet, vnis).await;
+        emunet_error(client, emunet, err).await;
+        return;
+    }
+    let res = client.bulk_set_edge_properties(
+        edges.iter().map(
+            |e| {
+                (e.edge_key(&emunet.vertex_type()), serde_json::to_value(e).unwrap())
+            }
+        )
+    ).await;
+    if let Err(err) = res {
+        let err = EmuNetError::DatabaseFail(format!("{:?}", err));
+        release_tunnels(&client, &mut emunet, vnis).await;
+        emunet_error(client, emunet, err).await;
+        return;
+    }
+
+    // launch the new devices and create the new links on the servers
+    let mut assignments: HashMap<Uuid, ServerAssignment> = emunet.servers().fold(HashMap::new(), |mut map, cs| {
+        map.insert(cs.id(), ServerAssignment::new(cs.conn_addr()));
+        map
+    });
+    for v in vertexes_map.values() {
+        assignments.get_mut(&v.server_uuid()).unwrap().add_device(v);
+    }
+    for e in edges.iter() {
+        let edge_uuid = e.edge_uuid();
+        let servers = (server_uuids.get(&edge_uuid.0).unwrap(), server_uuids.get(&edge_uuid.1).unwrap());
+        assignments.get_mut(servers.0).unwrap().add_link(e);
+        if servers.0 != servers.1 {
+            assignments.get_mut(servers.1).unwrap().add_link(e);
+        }
+    }
+    let launch_results = future::join_all(assignments.into_iter().filter(|(_, assignment)| {!assignment.is_empty()}).map(|(_, assignment)| {
+        container_backend::launch(emunet.vertex_type(), assignment)
+    })).await;
+
+    // record the launch result of each new device
+    let mut failed_devs = 0;
+    for (vertex_uuid, state) in launch_results.into_iter().flatten() {
+        if let DeviceState::Failed(_) = state {
+            failed_devs += 1;
+        }
+        vertexes_map.get_mut(&vertex_uuid).unwrap().set_state(state);
+    }
+    if failed_devs > 0 {
+        failures.push(format!("{} devices fail to launch", failed_devs));
+    }
+    let res = client.bulk_set_vertex_properties(
+        vertexes_map.values().map(
+            |v| {
+                (v.uuid(), serde_json::to_value(v).unwrap())
+            }
+        )
+    ).await;
+    if let Err(err) = res {
+        let err = EmuNetError::DatabaseFail(format!("{:?}", err));
+        emunet_error(client, emunet, err).await;
+        return;
+    }
+
+    // set the state of the emunet to normal if all the operations succeed
+    if failures.is_empty() {
+        emunet.normal();
+    }
+    else {
+        emunet.error(EmuNetError::LaunchFail(failures.join(", ")));
+    }
+    // store the vertex mappings in to the emunet
+    for v in vertexes_map.values() {
+        emunet.add_vertex(v.id(), v.uuid());
+    }
+
+    // store the state in the database
+    let uuid = emunet.uuid().clone();
+    if let Err(err) = store_emunet(&client, emunet).await {
+        log::error!("fail to store emunet {}: {}", uuid, err);
+    }
+}
+
+// put an emunet that is not modified back into its state before the update
+async fn restore_emunet(client: &Client, prev: EmuNet) {
+    let uuid = prev.uuid().clone();
+    if let Err(err) = store_emunet(client, prev).await {
+        log::error!("fail to restore emunet {}: {}", uuid, err);
+    }
+}
+
+// path/update_emunet/
+async fn update_emunet(json: Json, db_client: Client) -> Result<impl warp::Reply, warp::Rejection> {
+    // atomically move the emunet into working state, so that the concurrent updates are refused
+    let prev = extract_response!(
+        db_client.update_emu_net(json.emunet_uuid.clone(), |emunet| {
+            // only a running emunet without errors can be modified
+            if !emunet.is_normal() {
+                return Err("EmuNet can only be updated in normal state".to_string());
+            }
+            let prev = emunet.clone();
+            emunet.working();
+            Ok(prev)
+        }).await,
+        "internal_server_error",
+        "operation_fail"
+    );
+    let mut emunet = prev.clone();
+    emunet.working();
+
+    // validate the modification against the stored graph
+    let (num_add, num_del) = (json.add_devs.len() as u32, json.del_devs.len() as u32);
+    let res = match db_client.get_emu_net_graph(&emunet).await {
+        Ok(Ok((vertexes, edges))) => build_diff(json, vertexes, edges, &mut emunet).map_err(|err| {
+            with_status(format!("{{ \"invalid_input_graph\": \"{}\" }}", err), StatusCode::BAD_REQUEST)
+        }),
+        Ok(Err(err)) => Err(with_status(format!("{{ \"operation_fail\": \"{}\" }}", err), StatusCode::BAD_REQUEST)),
+        Err(err) => Err(with_status(format!("{{ \"internal_server_error\": \"{}\" }}", err), StatusCode::INTERNAL_SERVER_ERROR)),
+    };
+    let diff = match res {
+        Ok(diff) => diff,
+        Err(reply) => {
+            // the emunet is not modified, put it back into normal state
+            restore_emunet(&db_client, prev).await;
+            return Ok(reply);
+        }
+    };
+
+    // update the capacity of the emunet object
+    emunet.release_capacity(num_del);
+    emunet.reserve_capacity(num_add);
+    if let Err(err) = store_emunet(&db_client, emunet.clone()).await {
+        restore_emunet(&db_client, prev).await;
+        return Ok(with_status(format!("{{ \"internal_server_error\": \"{}\" }}", err), StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    // do the actual update work in the background
+    tokio::spawn(background_task(db_client, emunet, diff));
+
+    // reply to the client
+    Ok(warp::reply::with_status(format!("{{ \"status\": \"working\" }}"), http::StatusCode::OK))
+}
+
+/// This filter modifies the topology of an initialized emunet.
+/// The devices and links are added or removed according to the diff in the request,
+/// the new devices are placed on the remaining capacity of the servers of the emunet.
+pub fn build_filter(db_client: Client)
+    -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static
+{
+    let db_filter = warp::any().map(move || {
+        let clone = db_client.clone();
+        clone
+    });
+    warp::post()
+        .and(warp::path("v1"))
+        .and(warp::path("update_emunet"))
+        .and(warp::path::end())
+        .and(super::parse_json_body())
+        .and(db_filter)
+        .and_then(update_emunet)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use warp::Reply;
+
+    use super::*;
+    use crate::database::ClientLauncher;
+    use crate::emunet::server::{AllocationPolicy, ContainerServer, ServerInfoList};
+
+    fn dev(id: u64) -> VertexInfo {
+        serde_json::from_value(json!({"id": id, "description": format!("dev{}", id)})).unwrap()
+    }
+
+    fn diff_json(emunet: &EmuNet, mut diff: serde_json::Value) -> Json {
+        diff["emunet_uuid"] = json!(emunet.uuid());
+        serde_json::from_value(diff).unwrap()
+    }
+
+    // an emunet of capacity 6 on two servers of 3 containers, devices 1 and 2 are on the 
+    // first server and device 3 is on the second one, with the links (1, 2), (2, 3) and 
+    // a parallel link (3, 2)
+    fn stored() -> (EmuNet, Vec<Vertex>, Vec<Edge>) {
+        let mut sp = ServerInfoList::new();
+        sp.add_server_info("127.0.0.1", 10000, "10.0.0.1", "10.1.0.1", 3, 3000, 3072).unwrap();
+        sp.add_server_info("127.0.0.2", 10000, "10.0.0.2", "10.1.0.2", 3, 3000, 3072).unwrap();
+        let mut servers = sp.allocate_servers(6, AllocationPolicy::FirstFit).unwrap();
+        let server_ids = (servers[0].id(), servers[1].id());
+        let mut emunet = EmuNet::new("user".to_string(), "net".to_string(), indradb::util::generate_uuid_v1(), 6);
+
+        let vertexes: Vec<Vertex> = vec!((1, server_ids.0), (2, server_ids.0), (3, server_ids.1)).into_iter().map(|(id, server_id)| {
+            Vertex::new(dev(id), indradb::util::generate_uuid_v1(), server_id)
+        }).collect();
+        for v in vertexes.iter() {
+            assert!(servers.iter_mut().find(|cs| {cs.id() == v.server_uuid()}).unwrap().fill(v.resources()));
+            emunet.add_vertex(v.id(), v.uuid());
+        }
+        emunet.add_servers(servers);
+        emunet.reserve_capacity(3);
+        emunet.normal();
+
+        let uuid_of = |id: usize| {vertexes[id - 1].uuid()};
+        let mut edges = vec!(
+            Edge::new((uuid_of(1), uuid_of(2)), "link12".to_string(), LinkAttrs::default(), Some((0, 0))),
+            Edge::new((uuid_of(2), uuid_of(3)), "link23".to_string(), LinkAttrs::default(), Some((1, 0))),
+            Edge::new((uuid_of(3), uuid_of(2)), "link32".to_string(), LinkAttrs::default(), Some((1, 2))),
+        );
+        assign_link_indexes(std::iter::empty(), &mut edges);
+        (emunet, vertexes, edges)
+    }
+
+    // the servers of the emunet in the order of their addresses
+    fn servers(emunet: &EmuNet) -> Vec<&ContainerServer> {
+        let mut servers: Vec<&ContainerServer> = emunet.servers().collect();
+        servers.sort_by_key(|cs| {cs.conn_addr()});
+        servers
+    }
+
+    fn remaining(emunet: &EmuNet) -> Vec<u32> {
+        servers(emunet).into_iter().map(|cs| {cs.curr_capacity()}).collect()
+    }
+
+    fn devs(ids: std::ops::Range<u64>) -> serde_json::Value {
+        ids.map(|id| {json!({"id": id, "description": format!("dev{}", id)})}).collect()
+    }
+
+    fn descriptions(edges: &Vec<Edge>) -> Vec<String> {
+        let mut descriptions: Vec<String> = edges.iter().map(|e| {e.description()}).collect();
+        descriptions.sort();
+        descriptions
+    }
+
+    #[test]
+    fn devices_are_added_and_removed() {
+        let (mut emunet, vertexes, edges) = stored();
+        let server_ids: Vec<Uuid> = servers(&emunet).into_iter().map(|cs| {cs.id()}).collect();
+        let json = diff_json(&emunet, json!({
+            "del_devs": [1],
+            "add_devs": [{"id": 4, "description": "dev4"}, {"id": 5, "description": "dev5"}],
+            "add_links": [
+                {"edge_id": [4, 2], "description": "link42"},
+                {"edge_id": [5, 4], "description": "link54"}
+            ]
+        }));
+        let diff = build_diff(json, vertexes, edges, &mut emunet).unwrap();
+
+        // the links of the removed device are dropped together with it
+        assert_eq!(diff.del_devs.iter().map(|v| {v.id()}).collect::<Vec<_>>(), vec!(1));
+        assert!(diff.del_links.is_empty());
+        assert_eq!(descriptions(&diff.dropped_links), vec!("link12".to_string()));
+        assert_eq!(descriptions(&diff.kept_links), vec!("link23".to_string(), "link32".to_string()));
+        let mut kept_devs: Vec<u64> = diff.placement.keys().cloned().collect();
+        kept_devs.sort();
+        assert_eq!(kept_devs, vec!(2, 3));
+
+        // the new devices are placed next to their neighbours, on the container freed by device 1
+        let placed: Vec<(u64, Uuid)> = diff.add_devs.iter().map(|(vi, server_id)| {(vi.id(), *server_id)}).collect();
+        assert_eq!(placed, vec!((4, server_ids[0]), (5, server_ids[0])));
+        assert_eq!(remaining(&emunet), vec!(0, 2));
+
+        // the interfaces taken by the kept links are skipped, the ones of device 1 are reused
+        let ports: Vec<Option<(u32, u32)>> = diff.add_links.iter().map(|ei| {ei.ports()}).collect();
+        assert_eq!(ports, vec!(Some((0, 0)), Some((0, 1))));
+    }
+
+    #[test]
+    fn removed_device_must_exist() {
+        let (mut emunet, vertexes, edges) = stored();
+        let json = diff_json(&emunet, json!({"del_devs": [7]}));
+        assert_eq!(build_diff(json, vertexes, edges, &mut emunet).err().unwrap(), "device 7 does not exist");
+    }
+
+    #[test]
+    fn links_are_removed_by_reference() {
+        // a link is found in either direction
+        let (mut emunet, vertexes, edges) = stored();
+        let json = diff_json(&emunet, json!({"del_links": [[2, 1]]}));
+        let diff = build_diff(json, vertexes, edges, &mut emunet).unwrap();
+        assert_eq!(descriptions(&diff.del_links), vec!("link12".to_string()));
+        assert!(diff.dropped_links.is_empty());
+        assert_eq!(descriptions(&diff.kept_links), vec!("link23".to_string(), "link32".to_string()));
+        assert!(diff.released_vnis.is_empty());
+
+        let (mut emunet, vertexes, edges) = stored();
+        let json = diff_json(&emunet, json!({"del_links": [[1, 3]]}));
+        assert_eq!(build_diff(json, vertexes, edges, &mut emunet).err().unwrap(), "link (1, 3) does not exist");
+
+        // the link removed together with one of its devices is dropped
+        let (mut emunet, vertexes, edges) = stored();
+        let json = diff_json(&emunet, json!({"del_devs": [1], "del_links": [{"edge_id": [1, 2], "ports": [0, 0]}]}));
+        let diff = build_diff(json, vertexes, edges, &mut emunet).unwrap();
+        assert!(diff.del_links.is_empty());
+        assert_eq!(descriptions(&diff.dropped_links), vec!("link12".to_string()));
+    }
+
+    #[test]
+    fn parallel_links_are_removed_by_ports() {
+        let (mut emunet, vertexes, edges) = stored();
+        let json = diff_json(&emunet, json!({"del_links": [[2, 3]]}));
+        assert_eq!(
+            build_diff(json, vertexes, edges, &mut emunet).err().unwrap(), 
+            "link (2, 3) has parallel links, specify its ports"
+        );
+
+        // the ports are given in the order of the edge id in the request
+        let (mut emunet, vertexes, edges) = stored();
+        let json = diff_json(&emunet, json!({"del_links": [{"edge_id": [2, 3], "ports": [2, 1]}]}));
+        let diff = build_diff(json, vertexes, edges, &mut emunet).unwrap();
+        assert_eq!(descriptions(&diff.del_links), vec!("link32".to_string()));
+        assert_eq!(descriptions(&diff.kept_links), vec!("link12".to_string(), "link23".to_string()));
+
+        let (mut emunet, vertexes, edges) = stored();
+        let json = diff_json(&emunet, json!({"del_links": [{"edge_id": [2, 3], "ports": [0, 0]}]}));
+        assert_eq!(build_diff(json, vertexes, edges, &mut emunet).err().unwrap(), "link (2, 3) does not exist");
+    }
+
+    #[test]
+    fn parallel_links_are_added() {
+        let (mut emunet, vertexes, edges) = stored();
+        let json = diff_json(&emunet, json!({
+            "add_links": [
+                {"edge_id": [1, 3], "description": "link13"},
+                {"edge_id": [3, 1], "description": "link31"}
+            ]
+        }));
+        let diff = build_diff(json, vertexes, edges, &mut emunet).unwrap();
+        let ports: Vec<Option<(u32, u32)>> = diff.add_links.iter().map(|ei| {ei.ports()}).collect();
+        assert_eq!(ports, vec!(Some((1, 2)), Some((3, 2))));
+
+        // the parallel links count against the interfaces of a device
+        let (mut emunet, vertexes, edges) = stored();
+        let json = diff_json(&emunet, json!({
+            "add_devs": [{"id": 4, "description": "dev4", "total_ports": 1}],
+            "add_links": [
+                {"edge_id": [4, 1], "description": "link41"},
+                {"edge_id": [1, 4], "description": "link14"}
+            ]
+        }));
+        assert!(build_diff(json, vertexes, edges, &mut emunet).is_err());
+    }
+
+    #[test]
+    fn capacity_is_accounted() {
+        // the container of the removed device is reused
+        let (mut emunet, vertexes, edges) = stored();
+        let json = diff_json(&emunet, json!({
+            "del_devs": [3],
+            "add_devs": devs(4..8)
+        }));
+        let diff = build_diff(json, vertexes, edges, &mut emunet).unwrap();
+        assert_eq!(diff.add_devs.len(), 4);
+        assert_eq!(descriptions(&diff.dropped_links), vec!("link23".to_string(), "link32".to_string()));
+        assert_eq!(remaining(&emunet), vec!(0, 0));
+
+        let (mut emunet, vertexes, edges) = stored();
+        let json = diff_json(&emunet, json!({
+            "del_devs": [3],
+            "add_devs": devs(4..9)
+        }));
+        assert_eq!(
+            build_diff(json, vertexes, edges, &mut emunet).err().unwrap(), 
+            "the updated graph exceeds capacity limitation"
+        );
+        assert_eq!(remaining(&emunet), vec!(1, 2));
+
+        // a device is placed only on a server with enough cpu
+        let (mut emunet, vertexes, edges) = stored();
+        let json = diff_json(&emunet, json!({"add_devs": [{"id": 4, "description": "dev4", "cpu": 4000}]}));
+        assert_eq!(build_diff(json, vertexes, edges, &mut emunet).err().unwrap(), "device 4 does not fit in any server");
+    }
+
+    #[tokio::test]
+    async fn invalid_update_restores_normal_state() {
+        ClientLauncher::in_memory().with_db_client(|client| async move {
+            let mut sp = ServerInfoList::new();
+            sp.add_server_info("127.0.0.1", 10000, "10.0.0.1", "10.1.0.1", 10, 8000, 16384).unwrap();
+            client.init(sp.into_vec()).await.unwrap().unwrap();
+            client.register_user("user").await.unwrap().unwrap();
+            let uuid = client.create_emu_net("user".to_string(), "net".to_string(), 4, AllocationPolicy::FirstFit).await.unwrap().unwrap();
+            client.update_emu_net(uuid, |emunet| {
+                emunet.normal();
+                Ok(())
+            }).await.unwrap().unwrap();
+            let json = || {
+                serde_json::from_value::<Json>(json!({"emunet_uuid": uuid, "del_devs": [7]})).unwrap()
+            };
+
+            let resp = update_emunet(json(), client.clone()).await.unwrap().into_response();
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let emunet = client.get_emu_net(uuid).await.unwrap().unwrap();
+            assert!(emunet.is_normal());
+            assert_eq!(emunet.capacity(), 4);
+
+            // an emunet owned by another task is left untouched
+            client.update_emu_net(uuid, |emunet| {
+                emunet.working();
+                Ok(())
+            }).await.unwrap().unwrap();
+            let resp = update_emunet(json(), client.clone()).await.unwrap().into_response();
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            assert!(client.get_emu_net(uuid).await.unwrap().unwrap().is_working());
+            Ok(())
+        }).await.unwrap();
+    }
+}
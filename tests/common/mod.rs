@@ -0,0 +1,26 @@
+// Helpers shared by the integration tests.
+use std::net::SocketAddr;
+
+use tokio::time::{delay_for, Duration};
+
+use mocknet::autogen::container_agent::container_agent_server::ContainerAgentServer;
+use mocknet::container_agent::{AgentService, Driver};
+
+/// Start a container agent with the dry-run driver on a free local port,
+/// return the address once the agent accepts connections.
+pub async fn spawn_dry_run_agent() -> SocketAddr {
+    // let the OS pick a free port
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(ContainerAgentServer::new(AgentService::new(Driver::DryRun)))
+            .serve(addr)
+            .await
+            .unwrap();
+    });
+
+    while tokio::net::TcpStream::connect(&addr).await.is_err() {
+        delay_for(Duration::from_millis(10)).await;
+    }
+    addr
+}
@@ -0,0 +1,48 @@
+// Drive a container agent with the dry-run driver through the container backend.
+mod common;
+
+use serde_json::json;
+
+use mocknet::container_backend::{self, ServerAssignment};
+use mocknet::emunet::net::{DeviceState, Edge, LinkAttrs, Vertex, VertexInfo};
+use mocknet::emunet::server::{AllocationPolicy, ServerInfoList};
+
+#[tokio::test]
+async fn launch_and_remove_with_dry_run_agent() {
+    let agent_addr = common::spawn_dry_run_agent().await;
+
+    // reach the agent through the address of an allocated server
+    let mut sp = ServerInfoList::new();
+    sp.add_server_info("127.0.0.1", agent_addr.port(), "10.0.0.1", "10.1.0.1", 4, 8000, 16384).unwrap();
+    let cs = sp.allocate_servers(2, AllocationPolicy::BestFit).unwrap().pop().unwrap();
+    assert_eq!(cs.conn_addr(), agent_addr);
+
+    let vertexes: Vec<Vertex> = (1..3).map(|id| {
+        let vi: VertexInfo = serde_json::from_value(json!({"id": id, "description": "dev", "total_ports": 1})).unwrap();
+        Vertex::new(vi, indradb::util::generate_uuid_v1(), cs.id())
+    }).collect();
+    let edge = Edge::new((vertexes[0].uuid(), vertexes[1].uuid()), "link".to_string(), LinkAttrs::default(), Some((0, 0)));
+    let assignment = || {
+        let mut assignment = ServerAssignment::new(cs.conn_addr());
+        for v in vertexes.iter() {
+            assignment.add_device(v);
+        }
+        assignment.add_link(&edge);
+        assignment
+    };
+
+    let states = container_backend::launch("net".to_string(), assignment()).await;
+    assert_eq!(states.len(), 2);
+    assert!(states.values().all(|state| {match state {DeviceState::Running => true, _ => false}}));
+
+    // the devices can not be created twice
+    let states = container_backend::launch("net".to_string(), assignment()).await;
+    assert!(states.values().all(|state| {match state {DeviceState::Failed(_) => true, _ => false}}));
+
+    container_backend::remove("net".to_string(), assignment()).await.unwrap();
+    assert!(container_backend::remove("net".to_string(), assignment()).await.is_err());
+
+    // the devices can be launched again after the removal
+    let states = container_backend::launch("net".to_string(), assignment()).await;
+    assert!(states.values().all(|state| {match state {DeviceState::Running => true, _ => false}}));
+}
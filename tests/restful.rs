@@ -0,0 +1,95 @@
+// Drive the RESTful API through the warp filters, with the in-memory database
+// and a dry-run container agent.
+mod common;
+
+use serde_json::{json, Value};
+use tokio::time::{delay_for, Duration};
+use warp::Filter;
+
+use mocknet::database::{Client, ClientLauncher};
+use mocknet::emunet::server::ServerInfoList;
+use mocknet::restful::*;
+
+// send a POST request with a JSON body, return the status code and the parsed body
+async fn post<F>(filter: &F, path: &str, body: Value) -> (u16, Value)
+    where
+        F: Filter + 'static,
+        F::Extract: warp::Reply + Send,
+{
+    let resp = warp::test::request()
+        .method("POST")
+        .path(path)
+        .json(&body)
+        .reply(filter)
+        .await;
+    let status = resp.status().as_u16();
+    (status, serde_json::from_slice(resp.body()).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn emunet_lifecycle() {
+    let agent_addr = common::spawn_dry_run_agent().await;
+
+    ClientLauncher::in_memory().with_db_client(move |client: Client| async move {
+        let mut sp = ServerInfoList::new();
+        sp.add_server_info("127.0.0.1", agent_addr.port(), "10.0.0.1", "10.1.0.1", 10, 8000, 16384).unwrap();
+        client.init(sp.into_vec()).await.unwrap().unwrap();
+
+        let routes = register_user::build_filter(client.clone())
+            .or(create_emunet::build_filter(client.clone()))
+            .or(init_emunet::build_filter(client.clone()))
+            .or(get_emunet::build_filter(client.clone()))
+            .or(delete_emunet::build_filter(client.clone()));
+
+        let (status, _) = post(&routes, "/v1/register_user", json!({"name": "user"})).await;
+        assert_eq!(status, 200);
+        let (status, _) = post(&routes, "/v1/register_user", json!({"name": "user"})).await;
+        assert_eq!(status, 400);
+
+        let (status, body) = post(&routes, "/v1/create_emunet", json!({"user": "user", "emunet": "net", "capacity": 4})).await;
+        assert_eq!(status, 200);
+        let emunet_uuid = body["emunet_uuid"].clone();
+
+        // a graph larger than the capacity is rejected
+        let devs: Vec<Value> = (1..6).map(|id| {json!({"id": id, "description": "dev"})}).collect();
+        let (status, _) = post(&routes, "/v1/init_emunet", json!({"emunet_uuid": emunet_uuid, "devs": devs, "links": []})).await;
+        assert_eq!(status, 400);
+
+        let init = json!({
+            "emunet_uuid": emunet_uuid,
+            "devs": [{"id": 1, "description": "dev1"}, {"id": 2, "description": "dev2"}, {"id": 3, "description": "dev3"}],
+            "links": [{"edge_id": [1, 2], "description": "link12"}, {"edge_id": [2, 3], "description": "link23"}],
+        });
+        let (status, _) = post(&routes, "/v1/init_emunet", init.clone()).await;
+        assert_eq!(status, 201);
+        let (status, _) = post(&routes, "/v1/init_emunet", init).await;
+        assert_eq!(status, 400);
+
+        // wait for the background initialization
+        let mut body = Value::Null;
+        for _ in 0..100 {
+            let (status, resp) = post(&routes, "/v1/get_emunet", json!({"emunet_uuid": emunet_uuid})).await;
+            assert_eq!(status, 200);
+            body = resp;
+            if body["emunet"]["state"] != json!("Working") {
+                break;
+            }
+            delay_for(Duration::from_millis(50)).await;
+        }
+        assert_eq!(body["emunet"]["state"], json!("Normal"));
+        assert_eq!(body["vertex_infos"].as_array().unwrap().len(), 3);
+        assert_eq!(body["edge_infos"].as_array().unwrap().len(), 2);
+
+        let (status, _) = post(&routes, "/v1/delete_emunet", json!({"user": "user", "emunet": "net"})).await;
+        assert_eq!(status, 200);
+        let (status, _) = post(&routes, "/v1/get_emunet", json!({"emunet_uuid": emunet_uuid})).await;
+        assert_eq!(status, 400);
+        let (status, _) = post(&routes, "/v1/delete_emunet", json!({"user": "user", "emunet": "net"})).await;
+        assert_eq!(status, 400);
+
+        // the capacity is returned to the server pool
+        let servers = client.list_servers().await.unwrap().unwrap();
+        assert_eq!(servers[0].remaining_capacity(), 10);
+        Ok(())
+    }).await.unwrap();
+}